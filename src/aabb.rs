@@ -0,0 +1,91 @@
+use std::f64;
+
+use prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // Contains nothing, it is the identity of `union`.
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |aabb, &point| aabb.grow(point))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(self.min.x.min(other.min.x),
+                           self.min.y.min(other.min.y),
+                           self.min.z.min(other.min.z)),
+            max: Vec3::new(self.max.x.max(other.max.x),
+                           self.max.y.max(other.max.y),
+                           self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn grow(&self, point: Vec3) -> Aabb {
+        self.union(&Aabb::new(point, point))
+    }
+
+    pub fn translate(&self, offset: Vec3) -> Aabb {
+        Aabb::new(self.min + offset, self.max + offset)
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.extent();
+        if extent.x < 0.0 || extent.y < 0.0 || extent.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    pub fn longest_axis(&self) -> u32 {
+        let extent = self.extent();
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test, `inverse_direction` is `1 / ray.direction` per component so that it can be
+    // computed once per ray instead of once per box.
+    pub fn hit(&self, origin: Vec3, inverse_direction: Vec3, t_min: f64, t_max: f64) -> Option<f64> {
+        let mut t_near = t_min;
+        let mut t_far = t_max;
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+            let (t0, t1) = if t0 <= t1 { (t0, t1) } else { (t1, t0) };
+            t_near = t_near.max(t0);
+            t_far = t_far.min(t1);
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some(t_near)
+    }
+}
@@ -19,10 +19,10 @@ impl Keyframe {
         positions: Vec<Vec3>
     ) -> Keyframe {
         Keyframe {
-            t: t,
-            camera_position: camera_position,
-            camera_look_at: camera_look_at,
-            positions: positions,
+            t,
+            camera_position,
+            camera_look_at,
+            positions,
         }
     }
 }
@@ -35,7 +35,7 @@ pub struct Keyframes {
 impl Keyframes {
 
     pub fn new(frames: Vec<Keyframe>) -> Keyframes {
-        Keyframes { frames: frames }
+        Keyframes { frames }
     }
 
     fn get_or_last(&self, i: usize) -> Keyframe {
@@ -65,7 +65,7 @@ impl Keyframes {
                 return prev_vec + (delta * p);
             }
         }
-        return Vec3::new(0.0, 0.0, 0.0);
+        Vec3::new(0.0, 0.0, 0.0)
     }
 
     fn cam(&self, t: usize) -> Vec3 {
@@ -85,7 +85,7 @@ impl Keyframes {
                 return prev_vec + (delta * p);
             }
         }
-        return Vec3::new(0.0, 0.0, 0.0);
+        Vec3::new(0.0, 0.0, 0.0)
     }
 
     fn look(&self, t: usize) -> Vec3 {
@@ -105,14 +105,14 @@ impl Keyframes {
                 return prev_vec + (delta * p);
             }
         }
-        return Vec3::new(0.0, 0.0, 0.0);
+        Vec3::new(0.0, 0.0, 0.0)
     }
 }
 
 pub fn animate(scene: &Scene, camera: &Camera, frames: &Keyframes, t: usize) -> (Scene, Camera) {
     let mut shapes = Vec::new();
-    for s in 0..scene.shapes.len() {
-        shapes.push(scene.shapes[s].move_to(frames.pos(t, s)));
+    for (s, shape) in scene.shapes().iter().enumerate() {
+        shapes.push(shape.move_to(frames.pos(t, s)));
     }
     (Scene::new(shapes), camera.look_at(frames.look(t)).move_to(frames.cam(t)))
 }
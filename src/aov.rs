@@ -0,0 +1,152 @@
+use prelude::*;
+
+// The arbitrary output variables (AOVs) a film made `with_aovs` keeps next to the radiance. All
+// but the sample count and the light belong to the first surface the camera rays of a pixel hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ShapeId,
+    MaterialId,
+    SampleCount,
+    Direct,
+    Indirect,
+}
+
+impl Aov {
+    // The names accepted by `from_name`, which are also the names of their layers.
+    pub const NAMES: [&'static str; 9] =
+        ["albedo", "normal", "depth", "position", "shape", "material", "samples", "direct", "indirect"];
+
+    pub const ALL: [Aov; 9] = [Aov::Albedo,
+                               Aov::Normal,
+                               Aov::Depth,
+                               Aov::Position,
+                               Aov::ShapeId,
+                               Aov::MaterialId,
+                               Aov::SampleCount,
+                               Aov::Direct,
+                               Aov::Indirect];
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::NAMES.iter().position(|&other| other == name).map(|index| Aov::ALL[index])
+    }
+
+    pub fn name(&self) -> &'static str {
+        Aov::NAMES[Aov::ALL.iter().position(|aov| aov == self).unwrap()]
+    }
+
+    // The channels of the layer of the variable.
+    pub fn channels(&self) -> Vec<&'static str> {
+        match *self {
+            Aov::Albedo | Aov::Direct | Aov::Indirect => vec!["R", "G", "B"],
+            Aov::Normal | Aov::Position => vec!["X", "Y", "Z"],
+            Aov::Depth => vec!["Z"],
+            Aov::ShapeId | Aov::MaterialId => vec!["id"],
+            Aov::SampleCount => vec!["Y"],
+        }
+    }
+}
+
+// The sums of the output variables of a pixel. The direct and indirect light are spread over the
+// pixels like the radiance, and divided by the same weight. The albedo and normal are averaged
+// over the samples, the depth and position over the samples that hit anything. A pixel can see
+// more than one shape, so its shape and material are the ones its first sample to hit anything
+// saw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AovPixel {
+    pub direct: Color,
+    pub indirect: Color,
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+    pub position: Vec3,
+    pub hits: u32,
+    // The index of the shape in the scene, and the id of its material (`Scene::material_id`).
+    pub shape: Option<u32>,
+    pub material: Option<u32>,
+}
+
+impl AovPixel {
+    pub fn new() -> AovPixel {
+        AovPixel {
+            direct: Color::black(),
+            indirect: Color::black(),
+            albedo: Color::black(),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            depth: 0.0,
+            position: Vec3::new(0.0, 0.0, 0.0),
+            hits: 0,
+            shape: None,
+            material: None,
+        }
+    }
+
+    // Adds the samples of `other`, which were taken after the samples of this pixel.
+    pub fn merge(&mut self, other: &AovPixel) {
+        self.direct = self.direct + other.direct;
+        self.indirect = self.indirect + other.indirect;
+        self.albedo = self.albedo + other.albedo;
+        self.normal = self.normal + other.normal;
+        self.depth += other.depth;
+        self.position = self.position + other.position;
+        if self.hits == 0 {
+            self.shape = other.shape;
+            self.material = other.material;
+        }
+        self.hits += other.hits;
+    }
+}
+
+impl Default for AovPixel {
+    fn default() -> AovPixel {
+        AovPixel::new()
+    }
+}
+
+// A color for an id that is easy to tell apart from the colors of the ids next to it.
+pub fn id_color(id: u32) -> Color {
+    let mut hash = id.wrapping_add(1).wrapping_mul(0x9e37_79b9);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    let channel = |shift: u32| 0.2 + 0.8 * ((hash >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::{Aov, AovPixel, id_color};
+
+    #[test]
+    fn names_should_give_back_the_same_variable() {
+        for &aov in Aov::ALL.iter() {
+            assert_that!(Aov::from_name(aov.name()), is(equal_to(Some(aov))));
+        }
+        assert_that!(Aov::from_name("beauty"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn merged_pixel_should_keep_the_ids_of_its_first_hit() {
+        let mut first = AovPixel::new();
+        let mut second = AovPixel::new();
+        second.hits = 1;
+        second.shape = Some(2);
+        second.material = Some(1);
+        let mut third = second;
+        third.shape = Some(5);
+
+        first.merge(&second);
+        first.merge(&third);
+
+        assert_that!(first.hits, is(equal_to(2)));
+        assert_that!(first.shape, is(equal_to(Some(2))));
+        assert_that!(first.material, is(equal_to(Some(1))));
+        assert_that!(id_color(0), is(not(equal_to(id_color(1)))));
+        assert_that!(id_color(3), is(equal_to(id_color(3))));
+    }
+}
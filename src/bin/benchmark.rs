@@ -0,0 +1,74 @@
+extern crate raytracer;
+extern crate rand;
+
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use raytracer::prelude::*;
+
+const NUM_SPHERES: usize = 100_000;
+const NUM_RAYS: usize = 1_000;
+
+fn create_scene(rng: &mut XorShiftRng) -> Scene {
+    let mut shapes: Vec<Box<dyn Intersectable>> = Vec::with_capacity(NUM_SPHERES);
+    for _ in 0..NUM_SPHERES {
+        let origin = Vec3::new(rng.gen_range(-50.0, 50.0),
+                               rng.gen_range(-50.0, 50.0),
+                               rng.gen_range(-50.0, 50.0));
+        shapes.push(Box::new(Sphere::new(origin, rng.gen_range(0.05, 0.5), Color::white())));
+    }
+    Scene::new(shapes)
+}
+
+fn create_rays(rng: &mut XorShiftRng) -> Vec<Ray> {
+    (0..NUM_RAYS)
+        .map(|_| {
+            let direction = Vec3::new(rng.gen_range(-1.0, 1.0),
+                                      rng.gen_range(-1.0, 1.0),
+                                      rng.gen_range(-1.0, 1.0));
+            Ray::new(Vec3::new(0.0, 0.0, 80.0), direction.normalize())
+        })
+        .collect()
+}
+
+fn linear_search(scene: &Scene, ray: &Ray) -> Option<f64> {
+    let mut closest_so_far = f64::MAX;
+    let mut distance = None;
+    for shape in scene.shapes() {
+        if let Some(intersection) = shape.intersects(ray, 0.0, closest_so_far) {
+            closest_so_far = intersection.distance;
+            distance = Some(intersection.distance);
+        }
+    }
+    distance
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let mut rng = XorShiftRng::from_seed([7, 11, 13, 17]);
+
+    let start = Instant::now();
+    let scene = create_scene(&mut rng);
+    println!("Built BVH over {} spheres in {:.1} ms", NUM_SPHERES, millis(start.elapsed()));
+
+    let rays = create_rays(&mut rng);
+
+    let start = Instant::now();
+    let linear: Vec<Option<f64>> = rays.iter().map(|ray| linear_search(&scene, ray)).collect();
+    let linear_time = millis(start.elapsed());
+
+    let start = Instant::now();
+    let bvh: Vec<Option<f64>> = rays.iter()
+        .map(|ray| scene.intersects(ray, 0.0, f64::MAX).map(|i| i.distance))
+        .collect();
+    let bvh_time = millis(start.elapsed());
+
+    assert!(linear == bvh, "the BVH and the linear search disagree");
+    let hits = bvh.iter().filter(|distance| distance.is_some()).count();
+    println!("{} rays, {} hits", NUM_RAYS, hits);
+    println!("Linear search: {:10.2} ms ({:.3} ms/ray)", linear_time, linear_time / NUM_RAYS as f64);
+    println!("BVH:           {:10.2} ms ({:.3} ms/ray)", bvh_time, bvh_time / NUM_RAYS as f64);
+    println!("Speedup:       {:10.1}x", linear_time / bvh_time);
+}
@@ -3,10 +3,8 @@
 #![allow(unused_variables)]
 
 extern crate raytracer;
-extern crate bmp;
 
 use raytracer::prelude::*;
-use bmp::{Image, Pixel};
 
 fn create_camera(width: u32, height: u32) -> Camera {
     let origin = Vec3::new(0.0, 1.0, 2.0);
@@ -16,12 +14,17 @@ fn create_camera(width: u32, height: u32) -> Camera {
     let aspect_ratio = width as f64 / height as f64;
     let aperture = 0.0;
     let distance_to_focus = (origin - view_point).length();
-    panic!("Step 2a) Initialize and return a new Camera by calling its 'new' function with the \
-            parameters defined above");
+    Camera::new(origin,
+                view_point,
+                orthogonal_up,
+                vertical_field_of_view,
+                aspect_ratio,
+                aperture,
+                distance_to_focus)
 }
 
-fn create_scene() -> Scene {
-    Scene::new(vec![
+fn create_scene(mesh: Option<Mesh>) -> Scene {
+    let mut shapes: Vec<Box<dyn Intersectable>> = vec![
         Box::new(Sphere::refractive(
             Vec3::new(0.5, -0.2, -0.4),
             0.3,
@@ -54,34 +57,185 @@ fn create_scene() -> Scene {
             Vec3::new(0.0, -100.5, -1.0),
             100.0,
             Color::new(0.5, 0.8, 0.0))),
-    ])
+    ];
+    if let Some(mesh) = mesh {
+        shapes.push(Box::new(mesh));
+    }
+    Scene::new(shapes)
 }
 
 
-fn main() {
-    let (width, height, number_of_samples) = (600, 300, 1);
-    let camera = panic!("Step 2a) Initialize a camera by calling the 'create_camera()' \
-                         function with the width and height defined above");
-    let scene = create_scene();
+fn aov_path(output: &str, aov: Aov) -> std::path::PathBuf {
+    let output = std::path::Path::new(output);
+    let stem = output.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let name = match output.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, aov.name(), extension.to_string_lossy()),
+        None => format!("{}-{}", stem, aov.name()),
+    };
+    output.with_file_name(name)
+}
 
-    let pixels = raytracer::trace_scene(width, height, number_of_samples, &camera, &scene);
-    pixel_array_to_image(width, height, pixels)
+fn exit_with_usage() -> ! {
+    eprintln!("Usage: image [model.obj] [--threads N] [--seed N] [--sampler {}] [--filter {}] [--integrator {}] [--adaptive THRESHOLD] [--heatmap] [--passes N] [--checkpoint FILE] [--resume FILE] [--exposure EV] [--tone-map {}] [--denoise] [--output FILE] [--16-bit] [--aov {}]...",
+              SamplerKind::NAMES.join("|"),
+              Filter::NAMES.join("|"),
+              raytracer::integrator::NAMES.join("|"),
+              ToneMap::NAMES.join("|"),
+              Aov::NAMES.join("|"));
+    std::process::exit(1);
 }
 
-fn pixel_array_to_image(width: u32, height: u32, pixels: Vec<Color>) {
-    let mut image = Image::new(width, height);
-    for y in 0..height {
-        for x in 0..width {
-            image.set_pixel(x,
-                            y,
-                            to_pixel(pixels[(y * width + x) as usize]));
+fn main() {
+    let (width, height, number_of_samples) = (600, 300, 1);
+    let camera = create_camera(width, height);
+    let mut settings = RenderSettings::new(width, height, number_of_samples);
+
+    let mut mesh = None;
+    let mut integrator: Box<dyn Integrator> = Box::new(PathTracer::new());
+    let mut heatmap = false;
+    let mut passes = 1;
+    let mut checkpoint = None;
+    let mut resume = false;
+    let mut output = String::from("scene.bmp");
+    let mut sixteen_bit = false;
+    let mut exposure = 0.0;
+    let mut tone_mapping = ToneMap::default();
+    let mut aovs = Vec::new();
+    let mut denoising = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--threads" {
+            let num_threads = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| exit_with_usage());
+            settings = settings.with_num_threads(num_threads);
+        } else if arg == "--seed" {
+            let seed = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| exit_with_usage());
+            settings = settings.with_seed(seed);
+        } else if arg == "--sampler" {
+            let name = args.next().unwrap_or_else(|| exit_with_usage());
+            settings = settings.with_sampler(SamplerKind::from_name(&name).unwrap_or_else(|| exit_with_usage()));
+        } else if arg == "--filter" {
+            let name = args.next().unwrap_or_else(|| exit_with_usage());
+            settings = settings.with_filter(Filter::from_name(&name).unwrap_or_else(|| exit_with_usage()));
+        } else if arg == "--integrator" {
+            let name = args.next().unwrap_or_else(|| exit_with_usage());
+            integrator = raytracer::integrator::from_name(&name).unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--adaptive" {
+            let threshold = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| exit_with_usage());
+            settings = RenderSettings { num_samples: 64 * number_of_samples, ..settings }
+                .with_adaptive(4 * number_of_samples, threshold);
+        } else if arg == "--passes" {
+            passes = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--checkpoint" || arg == "--resume" {
+            checkpoint = Some(args.next().unwrap_or_else(|| exit_with_usage()));
+            resume = arg == "--resume";
+        } else if arg == "--exposure" {
+            exposure = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--tone-map" {
+            let name = args.next().unwrap_or_else(|| exit_with_usage());
+            tone_mapping = ToneMap::from_name(&name).unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--output" {
+            output = args.next().unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--16-bit" {
+            sixteen_bit = true;
+        } else if arg == "--aov" {
+            let name = args.next().unwrap_or_else(|| exit_with_usage());
+            aovs.push(Aov::from_name(&name).unwrap_or_else(|| exit_with_usage()));
+        } else if arg == "--denoise" {
+            denoising = true;
+        } else if arg == "--heatmap" {
+            heatmap = true;
+        } else if arg.starts_with("--") || mesh.is_some() {
+            exit_with_usage();
+        } else {
+            mesh = Some(load_obj(&arg).unwrap_or_else(|error| {
+                eprintln!("Could not load mesh: {}", error);
+                std::process::exit(1);
+            }));
         }
     }
-    let _ = image.save("scene.bmp");
-}
+    let scene = create_scene(mesh);
+    let format = match ImageFormat::from_path(&output) {
+        Some(ImageFormat::Png) if sixteen_bit => ImageFormat::Png16,
+        Some(format) => format,
+        None => exit_with_usage(),
+    };
+    let save = |film: &Film| {
+        let radiance = if denoising { denoise(film, &DenoiseSettings::new()) } else { film.radiance() };
+        let saved = match format {
+            ImageFormat::Exr => {
+                // The denoised image replaces the radiance, which is kept as the layer "noisy".
+                let mut layers = film.layers();
+                if denoising {
+                    let noisy = Layer { name: String::from("noisy"), ..layers[0].clone() };
+                    layers[0].values = radiance.iter()
+                        .flat_map(|c| vec![c.r as f32, c.g as f32, c.b as f32])
+                        .collect();
+                    layers.push(noisy);
+                }
+                save_exr(&output, width, height, &layers)
+            }
+            // Radiance HDR keeps the radiance itself, tone mapping it is up to whoever opens it.
+            ImageFormat::Hdr => save_image_as(&output, format, width, height, &radiance),
+            _ => {
+                let pixels = tone_map(&radiance, exposure, tone_mapping);
+                save_image_as(&output, format, width, height, &pixels)
+            }
+        };
+        if let Err(error) = saved {
+            eprintln!("Could not save {}: {}", output, error);
+        }
+        // Every `--aov` is saved as an image of its own next to the output, like scene-normal.png.
+        for &aov in &aovs {
+            let path = aov_path(&output, aov);
+            let saved = match format {
+                ImageFormat::Exr => save_exr(&path, width, height, &[film.aov_layer(aov)]),
+                _ => save_raw_image_as(&path, format, width, height, &film.aov_image(aov)),
+            };
+            if let Err(error) = saved {
+                eprintln!("Could not save {}: {}", path.display(), error);
+            }
+        }
+    };
 
-fn to_pixel(color: Color) -> Pixel {
-    Pixel::new((255.99 * color.r) as u8,
-               (255.99 * color.g) as u8,
-               (255.99 * color.b) as u8)
+    // Every pass adds more samples to the film, and updates the image so it can be previewed. With
+    // a checkpoint the film is also saved after every pass, and `--resume` carries on from there.
+    let hash = scene_hash(&scene, &camera);
+    let mut film = match checkpoint {
+        Some(ref path) if resume => {
+            load_checkpoint(path, &settings, &*integrator, hash).unwrap_or_else(|error| {
+                eprintln!("Could not resume from {}: {}", path, error);
+                std::process::exit(1);
+            })
+        }
+        // OpenEXR images also get a layer for every output variable, and the denoiser is guided by
+        // them.
+        _ if format == ImageFormat::Exr || !aovs.is_empty() || denoising => {
+            Film::new(width, height).with_aovs()
+        }
+        _ => Film::new(width, height),
+    };
+    if !aovs.is_empty() && !film.has_aovs() {
+        eprintln!("Could not resume: the checkpoint has no output variables, start again to save --aov");
+        std::process::exit(1);
+    }
+    let threads = RenderThreads::new(&settings);
+    for pass in film.passes..passes {
+        render_pass_on(&threads, &settings, &mut film, &camera, &scene, &*integrator);
+        if pass + 1 < passes {
+            save(&film);
+        }
+        if let Some(ref path) = checkpoint {
+            if let Err(error) = save_checkpoint(path, &settings, &*integrator, hash, &film) {
+                eprintln!("Could not write checkpoint {}: {}", path, error);
+            }
+        }
+        eprintln!("Pass {}/{} done", pass + 1, passes);
+    }
+    save(&film);
+    if heatmap {
+        let heatmap = sample_heatmap(&film.sample_counts());
+        if let Err(error) = save_raw_image("samples.bmp", width, height, &heatmap) {
+            eprintln!("Could not save samples.bmp: {}", error);
+        }
+    }
 }
@@ -3,7 +3,8 @@ extern crate gif;
 
 use raytracer::prelude::*;
 use gif::{Frame, Encoder, Repeat, SetParameter};
-use std::fs::File;
+use std::fs::{self, File};
+use std::path::PathBuf;
 
 fn create_camera(width: u32, height: u32) -> Camera {
     let origin = Vec3::new(0.0, 1.0, 2.0);
@@ -82,7 +83,43 @@ fn create_frames() -> Keyframes {
     ])
 }
 
+fn exit_with_usage() -> ! {
+    eprintln!("Usage: video [--checkpoint DIRECTORY] [--resume DIRECTORY] [--exposure EV] [--tone-map {}] [--denoise]",
+              ToneMap::NAMES.join("|"));
+    std::process::exit(1);
+}
+
 fn main() {
+    // Every finished frame is saved as a checkpoint in the directory, `--resume` only renders the
+    // frames that are missing.
+    let mut checkpoints = None;
+    let mut resume = false;
+    let mut exposure = 0.0;
+    let mut tone_mapping = ToneMap::default();
+    let mut denoising = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--checkpoint" || arg == "--resume" {
+            checkpoints = Some(PathBuf::from(args.next().unwrap_or_else(|| exit_with_usage())));
+            resume = arg == "--resume";
+        } else if arg == "--exposure" {
+            exposure = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--tone-map" {
+            let name = args.next().unwrap_or_else(|| exit_with_usage());
+            tone_mapping = ToneMap::from_name(&name).unwrap_or_else(|| exit_with_usage());
+        } else if arg == "--denoise" {
+            denoising = true;
+        } else {
+            exit_with_usage();
+        }
+    }
+    if let Some(ref directory) = checkpoints {
+        if let Err(error) = fs::create_dir_all(directory) {
+            eprintln!("Could not create {}: {}", directory.display(), error);
+            std::process::exit(1);
+        }
+    }
+
     let (width, height, number_of_samples) = (300, 150, 2);
     let orginal_camera = create_camera(width, height);
     let orginal_scene = create_scene();
@@ -92,26 +129,39 @@ fn main() {
     let mut encoder = Encoder::new(&mut image, width as u16, height as u16, &[]).unwrap();
     encoder.set(Repeat::Infinite).unwrap();
 
+    let integrator = PathTracer::new();
+    let threads = RenderThreads::new(&RenderSettings::new(width, height, number_of_samples));
     for i in 0..50 {
         let (scene, camera) = animate(&orginal_scene, &orginal_camera, &frames, i);
 
-        let mut pixels = pixels_to_vec(raytracer::trace_scene(width, height, number_of_samples, &camera, &scene));
+        let settings = RenderSettings::new(width, height, number_of_samples).with_frame(i as u32);
+        let hash = scene_hash(&scene, &camera);
+        let checkpoint = checkpoints.as_ref().map(|directory| directory.join(format!("frame-{:03}.checkpoint", i)));
+        let film = match checkpoint {
+            Some(ref path) if resume && path.exists() => {
+                load_checkpoint(path, &settings, &integrator, hash).unwrap_or_else(|error| {
+                    eprintln!("Could not resume from {}: {}", path.display(), error);
+                    std::process::exit(1);
+                })
+            }
+            _ => {
+                // The denoiser is guided by the output variables of the film.
+                let mut film = if denoising { Film::new(width, height).with_aovs() } else { Film::new(width, height) };
+                render_pass_on(&threads, &settings, &mut film, &camera, &scene, &integrator);
+                if let Some(ref path) = checkpoint {
+                    if let Err(error) = save_checkpoint(path, &settings, &integrator, hash, &film) {
+                        eprintln!("Could not write checkpoint {}: {}", path.display(), error);
+                    }
+                }
+                film
+            }
+        };
+        let radiance = if denoising { denoise(&film, &DenoiseSettings::new()) } else { film.radiance() };
+        let pixels = srgb_bytes(&tone_map(&radiance, exposure, tone_mapping));
 
-        let frame = Frame::from_rgb(width as u16, height as u16, &mut *pixels);
+        let frame = Frame::from_rgb(width as u16, height as u16, &pixels);
 
         // Write frame to file
         encoder.write_frame(&frame).unwrap();
     }
 }
-
-fn pixels_to_vec(pixels: Vec<Color>) -> Vec<u8> {
-    let mut vec : Vec<u8> = Vec::with_capacity(pixels.len() * 3);
-
-    for Color {r, g, b} in pixels {
-        vec.push((r * 255.0) as u8);
-        vec.push((g * 255.0) as u8);
-        vec.push((b * 255.0) as u8);
-    }
-
-    return vec;
-}
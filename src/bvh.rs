@@ -0,0 +1,254 @@
+use std::f64;
+
+use aabb::Aabb;
+use scene::Intersection;
+use prelude::*;
+
+const NUM_BINS: usize = 16;
+const MAX_PRIMITIVES_IN_LEAF: usize = 4;
+// Cost of visiting a node relative to the cost of intersecting a primitive.
+const TRAVERSAL_COST: f64 = 1.0;
+
+// A flattened node, the left child of an interior node is stored right after it.
+struct Node {
+    aabb: Aabb,
+    // First index of a leaf, or the node index of the right child of an interior node.
+    offset: usize,
+    count: usize,
+    axis: u32,
+}
+
+// A bounding volume hierarchy over primitives that are referred to by their index, so it can be
+// used for the shapes of a `Scene` and the faces of a `Mesh` alike. Primitives without a
+// bounding box are tested on every query.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(bounds: &[Option<Aabb>]) -> Bvh {
+        let mut indices = Vec::with_capacity(bounds.len());
+        let mut unbounded = Vec::new();
+        let mut aabbs = Vec::with_capacity(bounds.len());
+        for (index, aabb) in bounds.iter().enumerate() {
+            match *aabb {
+                Some(aabb) => indices.push(index),
+                None => unbounded.push(index),
+            }
+            aabbs.push(aabb.unwrap_or_else(Aabb::empty));
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * indices.len()),
+            indices: Vec::new(),
+            unbounded,
+        };
+        if !indices.is_empty() {
+            let centroids: Vec<Vec3> = aabbs.iter().map(|aabb| aabb.centroid()).collect();
+            let len = indices.len();
+            bvh.build(&aabbs, &centroids, &mut indices, 0, len);
+        }
+        bvh.indices = indices;
+        bvh
+    }
+
+    pub fn aabb(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        Some(self.nodes.first().map_or_else(Aabb::empty, |node| node.aabb))
+    }
+
+    // Finds the closest intersection, `intersects` is called with the index of a primitive and
+    // the closest distance found so far.
+    pub fn intersects<F>(&self, ray: &Ray, t_min: f64, t_max: f64, mut intersects: F) -> Option<Intersection>
+        where F: FnMut(usize, f64) -> Option<Intersection>
+    {
+        let mut intersection: Option<Intersection> = None;
+        let mut closest_so_far = t_max;
+
+        for &index in &self.unbounded {
+            if let Some(other_intersection) = intersects(index, closest_so_far) {
+                closest_so_far = other_intersection.distance;
+                intersection = Some(other_intersection);
+            }
+        }
+        if self.nodes.is_empty() {
+            return intersection;
+        }
+
+        let inverse_direction = Vec3::new(1.0 / ray.direction.x,
+                                          1.0 / ray.direction.y,
+                                          1.0 / ray.direction.z);
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.aabb.hit(ray.origin, inverse_direction, t_min, closest_so_far).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for &index in &self.indices[node.offset..node.offset + node.count] {
+                    if let Some(other_intersection) = intersects(index, closest_so_far) {
+                        closest_so_far = other_intersection.distance;
+                        intersection = Some(other_intersection);
+                    }
+                }
+            } else if inverse_direction[node.axis] < 0.0 {
+                // Visit the child closest to the ray first, it is popped last.
+                stack.push(node_index + 1);
+                stack.push(node.offset);
+            } else {
+                stack.push(node.offset);
+                stack.push(node_index + 1);
+            }
+        }
+        intersection
+    }
+
+    fn build(&mut self,
+             aabbs: &[Aabb],
+             centroids: &[Vec3],
+             indices: &mut [usize],
+             start: usize,
+             end: usize)
+             -> usize {
+        let node_index = self.nodes.len();
+        let aabb = indices[start..end].iter().fold(Aabb::empty(), |aabb, &i| aabb.union(&aabbs[i]));
+        self.nodes.push(Node {
+            aabb,
+            offset: start,
+            count: end - start,
+            axis: 0,
+        });
+
+        let count = end - start;
+        if count == 1 {
+            return node_index;
+        }
+        let centroid_bounds = indices[start..end]
+            .iter()
+            .fold(Aabb::empty(), |bounds, &i| bounds.grow(centroids[i]));
+        let axis = centroid_bounds.longest_axis();
+        let axis_min = centroid_bounds.min[axis];
+        let axis_extent = centroid_bounds.max[axis] - axis_min;
+        if axis_extent <= 0.0 {
+            // Every centroid is in the same spot, there is no way to split them.
+            return node_index;
+        }
+
+        let bin_of = |i: usize| {
+            let bin = (NUM_BINS as f64 * (centroids[i][axis] - axis_min) / axis_extent) as usize;
+            bin.min(NUM_BINS - 1)
+        };
+        let mut bin_counts = [0usize; NUM_BINS];
+        let mut bin_bounds = [Aabb::empty(); NUM_BINS];
+        for &i in &indices[start..end] {
+            let bin = bin_of(i);
+            bin_counts[bin] += 1;
+            bin_bounds[bin] = bin_bounds[bin].union(&aabbs[i]);
+        }
+
+        // Surface area heuristic: the cost of a split is the expected number of primitive tests,
+        // weighted by the probability of a ray that hits the parent also hitting each child.
+        let mut best_split = 0;
+        let mut best_cost = f64::INFINITY;
+        for split in 1..NUM_BINS {
+            let (left_count, left_bounds) = sum_bins(&bin_counts[..split], &bin_bounds[..split]);
+            let (right_count, right_bounds) = sum_bins(&bin_counts[split..], &bin_bounds[split..]);
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST +
+                       (left_count as f64 * left_bounds.surface_area() +
+                        right_count as f64 * right_bounds.surface_area()) /
+                       aabb.surface_area().max(f64::EPSILON);
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+        if best_split == 0 || (count <= MAX_PRIMITIVES_IN_LEAF && best_cost >= count as f64) {
+            return node_index;
+        }
+
+        let mut middle = start;
+        for i in start..end {
+            if bin_of(indices[i]) < best_split {
+                indices.swap(i, middle);
+                middle += 1;
+            }
+        }
+
+        self.build(aabbs, centroids, indices, start, middle);
+        let right = self.build(aabbs, centroids, indices, middle, end);
+        let node = &mut self.nodes[node_index];
+        node.offset = right;
+        node.count = 0;
+        node.axis = axis;
+        node_index
+    }
+}
+
+fn sum_bins(counts: &[usize], bounds: &[Aabb]) -> (usize, Aabb) {
+    counts.iter()
+        .zip(bounds)
+        .fold((0, Aabb::empty()), |(count, aabb), (&c, b)| (count + c, aabb.union(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    fn random_vec(rng: &mut XorShiftRng, scale: f64) -> Vec3 {
+        Vec3::new(rng.gen_range(-scale, scale),
+                  rng.gen_range(-scale, scale),
+                  rng.gen_range(-scale, scale))
+    }
+
+    #[test]
+    fn bvh_should_find_the_same_intersections_as_a_linear_search() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut shapes: Vec<Box<dyn Intersectable>> = Vec::new();
+        for _ in 0..500 {
+            let origin = random_vec(&mut rng, 10.0);
+            let radius = rng.gen_range(0.05, 0.5);
+            shapes.push(Box::new(Sphere::new(origin, radius, Color::white())));
+        }
+        for _ in 0..100 {
+            let a = random_vec(&mut rng, 10.0);
+            shapes.push(Box::new(Triangle::new(a,
+                                               a + random_vec(&mut rng, 1.0),
+                                               a + random_vec(&mut rng, 1.0),
+                                               Color::white())));
+        }
+        let scene = Scene::new(shapes);
+
+        for _ in 0..2000 {
+            let ray = Ray::new(random_vec(&mut rng, 12.0), random_vec(&mut rng, 1.0).normalize());
+
+            let linear = scene.shapes()
+                .iter()
+                .filter_map(|shape| shape.intersects(&ray, 0.0, 1000.0))
+                .map(|intersection| intersection.distance)
+                .fold(None, |closest: Option<f64>, distance| {
+                    Some(closest.map_or(distance, |closest| closest.min(distance)))
+                });
+            let bvh = scene.intersects(&ray, 0.0, 1000.0).map(|i| i.distance);
+
+            assert_that!(bvh, is(equal_to(linear)));
+        }
+    }
+
+    #[test]
+    fn scene_without_shapes_should_not_intersect() {
+        let scene = Scene::new(vec![]);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert_that!(scene.intersects(&ray, 0.0, 1000.0).is_none(), is(true));
+    }
+}
@@ -1,5 +1,4 @@
 use std::f64::consts::PI;
-use rand::{self, Rng};
 
 use prelude::*;
 
@@ -41,24 +40,36 @@ impl Camera {
         let horizontal = 2.0 * half_width * distance_to_focus * u;
         let vertical = 2.0 * half_height * distance_to_focus * v;
         Camera {
-            origin: origin,
-            lower_left_corner: lower_left_corner,
-            horizontal: horizontal,
-            vertical: vertical,
-            u: u,
-            v: v,
+            origin,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
             lens_radius: aperture / 2.0,
-            view_point: view_point,
-            orthogonal_up: orthogonal_up,
-            vertical_field_of_view: vertical_field_of_view,
-            aspect_ratio: aspect_ratio,
-            aperture: aperture,
-            distance_to_focus: distance_to_focus,
+            view_point,
+            orthogonal_up,
+            vertical_field_of_view,
+            aspect_ratio,
+            aperture,
+            distance_to_focus,
         }
     }
 
-    pub fn create_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = self.lens_radius * random_point_in_unit_disc();
+    // Feeds the parameters the camera was made from to the hasher, everything else follows from
+    // them.
+    pub fn write_hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_vec3(self.origin);
+        hasher.write_vec3(self.view_point);
+        hasher.write_vec3(self.orthogonal_up);
+        hasher.write_f64(self.vertical_field_of_view);
+        hasher.write_f64(self.aspect_ratio);
+        hasher.write_f64(self.aperture);
+        hasher.write_f64(self.distance_to_focus);
+    }
+
+    pub fn create_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * random_point_in_unit_disc(sampler);
         let offset = self.u * rd.x + self.v * rd.y;
         let direection = self.lower_left_corner + u * self.horizontal + v * self.vertical -
                          self.origin - offset;
@@ -86,12 +97,8 @@ impl Camera {
     }
 }
 
-fn random_point_in_unit_disc() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = 2.0 * Vec3::new(rng.next_f64(), rng.next_f64(), 0.0) - Vec3::new(1.0, 1.0, 0.0);
-        if p.dot(p) < 1.0 {
-            return p;
-        }
-    }
+fn random_point_in_unit_disc(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.next_2d();
+    let (r, theta) = (u1.sqrt(), 2.0 * PI * u2);
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
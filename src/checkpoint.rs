@@ -0,0 +1,447 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::path::Path;
+
+use film::{FilmPixel, RunningStats};
+use sampler::SamplerKind;
+use prelude::*;
+
+// A checkpoint holds everything needed to carry on with a render: the film (the sums and sample
+// counts of every pixel), the settings the samples are taken with, the integrator with its
+// settings, and a hash of the scene and camera so a checkpoint is never resumed with a scene it was
+// not rendered from.
+const MAGIC: &[u8; 8] = b"RTCKPT06";
+
+// Stands for the shape and material of pixels that no camera ray hit.
+const NO_ID: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    NotACheckpoint,
+    SceneChanged,
+    SettingsChanged(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheckpointError::Io(ref error) => write!(f, "{}", error),
+            CheckpointError::NotACheckpoint => write!(f, "not a render checkpoint"),
+            CheckpointError::SceneChanged => {
+                write!(f, "the scene or camera changed since the checkpoint was written")
+            }
+            CheckpointError::SettingsChanged(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> CheckpointError {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            CheckpointError::NotACheckpoint
+        } else {
+            CheckpointError::Io(error)
+        }
+    }
+}
+
+// Writes the checkpoint next to `path` first and then moves it in place, so a render that dies
+// while writing leaves the previous checkpoint intact.
+pub fn save_checkpoint<P: AsRef<Path>>(path: P,
+                                       settings: &RenderSettings,
+                                       integrator: &dyn Integrator,
+                                       scene_hash: u64,
+                                       film: &Film)
+                                       -> Result<(), CheckpointError> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        write_checkpoint(&mut writer, settings, integrator, scene_hash, film)?;
+        writer.flush()?;
+    }
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+// Reads the film of a checkpoint, as long as it was rendered from the same scene with settings
+// that make the same samplers, and with the same integrator.
+pub fn load_checkpoint<P: AsRef<Path>>(path: P,
+                                       settings: &RenderSettings,
+                                       integrator: &dyn Integrator,
+                                       scene_hash: u64)
+                                       -> Result<Film, CheckpointError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_checkpoint(&mut reader, settings, integrator, scene_hash)
+}
+
+pub fn write_checkpoint<W: Write>(writer: &mut W,
+                                  settings: &RenderSettings,
+                                  integrator: &dyn Integrator,
+                                  scene_hash: u64,
+                                  film: &Film)
+                                  -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u64(writer, scene_hash)?;
+    write_u32(writer, film.width)?;
+    write_u32(writer, film.height)?;
+    write_u64(writer, settings.seed)?;
+    write_u32(writer, settings.frame)?;
+    write_u32(writer, sampler_index(settings.sampler))?;
+    write_u32(writer, settings.num_samples)?;
+    write_string(writer, &format!("{:?}", settings.filter))?;
+    write_string(writer, &format!("{:?}", settings.adaptive))?;
+    write_string(writer, integrator.name())?;
+    write_string(writer, &format!("{:?}", integrator))?;
+    write_u32(writer, film.passes)?;
+    writer.write_all(&[film.has_aovs() as u8])?;
+    for y in 0..film.height {
+        for x in 0..film.width {
+            let pixel = film.pixel(x, y);
+            write_f64(writer, pixel.sum.r)?;
+            write_f64(writer, pixel.sum.g)?;
+            write_f64(writer, pixel.sum.b)?;
+            write_f64(writer, pixel.weight)?;
+            write_u32(writer, pixel.num_samples)?;
+            write_u64(writer, pixel.luminance.count)?;
+            write_f64(writer, pixel.luminance.mean)?;
+            write_f64(writer, pixel.luminance.sum_of_squares)?;
+            if film.has_aovs() {
+                let aov = film.aov(x, y);
+                for &color in [aov.direct, aov.indirect, aov.albedo].iter() {
+                    write_f64(writer, color.r)?;
+                    write_f64(writer, color.g)?;
+                    write_f64(writer, color.b)?;
+                }
+                write_f64(writer, aov.normal.x)?;
+                write_f64(writer, aov.normal.y)?;
+                write_f64(writer, aov.normal.z)?;
+                write_f64(writer, aov.depth)?;
+                write_f64(writer, aov.position.x)?;
+                write_f64(writer, aov.position.y)?;
+                write_f64(writer, aov.position.z)?;
+                write_u32(writer, aov.hits)?;
+                write_u32(writer, aov.shape.map_or(NO_ID, |id| id))?;
+                write_u32(writer, aov.material.map_or(NO_ID, |id| id))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn read_checkpoint<R: Read>(reader: &mut R,
+                                settings: &RenderSettings,
+                                integrator: &dyn Integrator,
+                                scene_hash: u64)
+                                -> Result<Film, CheckpointError> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(CheckpointError::NotACheckpoint);
+    }
+    if read_u64(reader)? != scene_hash {
+        return Err(CheckpointError::SceneChanged);
+    }
+    let (width, height) = (read_u32(reader)?, read_u32(reader)?);
+    check("image size",
+          format!("{}x{}", width, height),
+          format!("{}x{}", settings.width, settings.height))?;
+    check("seed", read_u64(reader)?, settings.seed)?;
+    check("frame", read_u32(reader)?, settings.frame)?;
+    let sampler = SamplerKind::NAMES.get(read_u32(reader)? as usize)
+        .ok_or(CheckpointError::NotACheckpoint)?;
+    check("sampler", *sampler, settings.sampler.name())?;
+    check("number of samples per pass", read_u32(reader)?, settings.num_samples)?;
+    check("filter", read_string(reader)?, format!("{:?}", settings.filter))?;
+    check("adaptive sampling", read_string(reader)?, format!("{:?}", settings.adaptive))?;
+    check("integrator", read_string(reader)?, integrator.name().to_string())?;
+    check("integrator settings", read_string(reader)?, format!("{:?}", integrator))?;
+
+    let mut film = Film::new(width, height);
+    film.passes = read_u32(reader)?;
+    let mut has_aovs = [0];
+    reader.read_exact(&mut has_aovs)?;
+    if has_aovs[0] != 0 {
+        film = film.with_aovs();
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let sum = Color::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?);
+            let weight = read_f64(reader)?;
+            let num_samples = read_u32(reader)?;
+            let luminance = RunningStats {
+                count: read_u64(reader)?,
+                mean: read_f64(reader)?,
+                sum_of_squares: read_f64(reader)?,
+            };
+            film.set_pixel(x, y, FilmPixel { sum, weight, num_samples, luminance });
+            if film.has_aovs() {
+                let mut colors = [Color::black(); 3];
+                for color in colors.iter_mut() {
+                    *color = Color::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?);
+                }
+                let aov = AovPixel {
+                    direct: colors[0],
+                    indirect: colors[1],
+                    albedo: colors[2],
+                    normal: Vec3::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?),
+                    depth: read_f64(reader)?,
+                    position: Vec3::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?),
+                    hits: read_u32(reader)?,
+                    shape: Some(read_u32(reader)?).filter(|&id| id != NO_ID),
+                    material: Some(read_u32(reader)?).filter(|&id| id != NO_ID),
+                };
+                film.set_aov(x, y, aov);
+            }
+        }
+    }
+    Ok(film)
+}
+
+fn check<T: PartialEq + fmt::Display>(name: &str, saved: T, current: T) -> Result<(), CheckpointError> {
+    if saved == current {
+        Ok(())
+    } else {
+        Err(CheckpointError::SettingsChanged(format!("the checkpoint was rendered with {} {}, not {}",
+                                                     name,
+                                                     saved,
+                                                     current)))
+    }
+}
+
+fn sampler_index(sampler: SamplerKind) -> u32 {
+    SamplerKind::NAMES.iter().position(|&name| name == sampler.name()).unwrap() as u32
+}
+
+// A hash of everything that decides what the image looks like: the camera, the background, and
+// every shape with its material, down to the vertices of meshes and the pixels of textures.
+pub fn scene_hash(scene: &Scene, camera: &Camera) -> u64 {
+    let mut hasher = SceneHasher::new();
+    camera.write_hash(&mut hasher);
+    scene.hash(&mut hasher);
+    hasher.finish()
+}
+
+// The 64 bit FNV-1a hash. Unlike the hasher of the standard library it is the same in every
+// version of Rust, so checkpoints survive a new compiler. Shapes and materials feed it their
+// parameters in `Intersectable::hash` and `Material::hash`, starting with their name so that two
+// kinds of shapes with the same numbers do not hash the same.
+pub struct SceneHasher(u64);
+
+impl SceneHasher {
+    pub fn new() -> SceneHasher {
+        SceneHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write_vec3(&mut self, vec: Vec3) {
+        self.write_f64(vec.x);
+        self.write_f64(vec.y);
+        self.write_f64(vec.z);
+    }
+
+    pub fn write_color(&mut self, color: Color) {
+        self.write_f64(color.r);
+        self.write_f64(color.g);
+        self.write_f64(color.b);
+    }
+
+    pub fn write_matrix(&mut self, matrix: Matrix4) {
+        for row in matrix.m.iter() {
+            for &value in row.iter() {
+                self.write_f64(value);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for SceneHasher {
+    fn default() -> SceneHasher {
+        SceneHasher::new()
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f64<W: Write>(writer: &mut W, value: f64) -> io::Result<()> {
+    write_u64(writer, value.to_bits())
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    read_u64(reader).map(f64::from_bits)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, CheckpointError> {
+    let length = read_u32(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length as usize {
+        return Err(CheckpointError::NotACheckpoint);
+    }
+    String::from_utf8(bytes).map_err(|_| CheckpointError::NotACheckpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(Vec3::new(0.0, 0.5, 2.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                    Vec3::new(0.0, 1.0, 0.0),
+                    40.0,
+                    1.0,
+                    0.0,
+                    3.0)
+    }
+
+    fn scene(radius: f64, color: Color) -> Scene {
+        Scene::new(vec![Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), radius, color)),
+                        Box::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, Color::white()))])
+    }
+
+    fn checkpoint(settings: &RenderSettings, film: &Film) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_checkpoint(&mut bytes, settings, &PathTracer::new(), 42, film).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resumed_render_should_match_an_uninterrupted_render() {
+        let settings = RenderSettings::new(8, 8, 2);
+        let (camera, scene) = (camera(), scene(0.5, Color::red()));
+        let mut uninterrupted = Film::new(8, 8).with_aovs();
+        render_pass(&settings, &mut uninterrupted, &camera, &scene, &PathTracer::new());
+        let saved = checkpoint(&settings, &uninterrupted);
+        render_pass(&settings, &mut uninterrupted, &camera, &scene, &PathTracer::new());
+
+        let mut resumed = read_checkpoint(&mut &saved[..], &settings, &PathTracer::new(), 42).unwrap();
+        render_pass(&settings, &mut resumed, &camera, &scene, &PathTracer::new());
+
+        assert_that!(resumed, is(equal_to(uninterrupted)));
+    }
+
+    #[test]
+    fn checkpoint_should_not_be_resumed_with_another_scene_or_settings() {
+        let settings = RenderSettings::new(4, 4, 2);
+        let saved = checkpoint(&settings, &Film::new(4, 4));
+
+        match read_checkpoint(&mut &saved[..], &settings, &PathTracer::new(), 43) {
+            Err(CheckpointError::SceneChanged) => (),
+            other => panic!("expected the scene to have changed, got {:?}", other),
+        }
+        match read_checkpoint(&mut &saved[..], &settings.with_seed(1), &PathTracer::new(), 42) {
+            Err(CheckpointError::SettingsChanged(_)) => (),
+            other => panic!("expected the settings to have changed, got {:?}", other),
+        }
+        match read_checkpoint(&mut &saved[..], &settings.with_adaptive(4, 0.05), &PathTracer::new(), 42) {
+            Err(CheckpointError::SettingsChanged(ref message)) if message.contains("adaptive") => (),
+            other => panic!("expected the adaptive sampling to have changed, got {:?}", other),
+        }
+        match read_checkpoint(&mut &saved[..], &settings, &Whitted::new(), 42) {
+            Err(CheckpointError::SettingsChanged(ref message)) if message.contains("integrator path") => (),
+            other => panic!("expected the integrator to have changed, got {:?}", other),
+        }
+        let shallow = PathTracer::new().with_max_depth(5);
+        match read_checkpoint(&mut &saved[..], &settings, &shallow, 42) {
+            Err(CheckpointError::SettingsChanged(ref message)) if message.contains("max_depth: 50") => (),
+            other => panic!("expected the integrator settings to have changed, got {:?}", other),
+        }
+        match read_checkpoint(&mut &saved[..saved.len() - 1], &settings, &PathTracer::new(), 42) {
+            Err(CheckpointError::NotACheckpoint) => (),
+            other => panic!("expected a truncated checkpoint, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn scene_hash_should_change_with_the_scene() {
+        let hash = scene_hash(&scene(0.5, Color::red()), &camera());
+
+        assert_that!(hash, is(equal_to(scene_hash(&scene(0.5, Color::red()), &camera()))));
+        assert_that!(hash == scene_hash(&scene(0.6, Color::red()), &camera()), is(false));
+        assert_that!(hash == scene_hash(&scene(0.5, Color::blue()), &camera()), is(false));
+        assert_that!(hash == scene_hash(&scene(0.5, Color::red()).with_background(Background::black()),
+                                        &camera()),
+                     is(false));
+    }
+
+    #[test]
+    fn scene_hash_should_change_with_edits_no_ray_would_find() {
+        let hash = |shapes: Vec<Box<dyn Intersectable>>| scene_hash(&Scene::new(shapes), &camera());
+        let triangle = |top: Vec3, color: Color| {
+            let positions = vec![Vec3::new(-1.0, 0.0, -2.0), Vec3::new(1.0, 0.0, -2.0), top];
+            let face = MeshFace { positions: [0, 1, 2], normals: None, uvs: None, material: 0 };
+            let mesh = Mesh::new(positions, vec![], vec![], vec![face], vec![::material::lambertian(color)])
+                .unwrap();
+            Box::new(mesh) as Box<dyn Intersectable>
+        };
+        let plane = |x: f64| {
+            Box::new(Plane::new(Vec3::new(x, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0), Color::white())) as
+            Box<dyn Intersectable>
+        };
+        let original = hash(vec![triangle(Vec3::new(0.0, 1.0, -2.0), Color::red()), plane(0.0)]);
+
+        // A vertex moved within the bounds of the mesh, the material of a mesh, and a plane moved
+        // along itself.
+        assert_that!(original == hash(vec![triangle(Vec3::new(0.1, 1.0, -2.0), Color::red()), plane(0.0)]),
+                     is(false));
+        assert_that!(original == hash(vec![triangle(Vec3::new(0.0, 1.0, -2.0), Color::blue()), plane(0.0)]),
+                     is(false));
+        assert_that!(original == hash(vec![triangle(Vec3::new(0.0, 1.0, -2.0), Color::red()), plane(1.0)]),
+                     is(false));
+    }
+}
@@ -9,7 +9,7 @@ pub struct Color {
 
 impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Color {
-        Color { r: r, g: g, b: b }
+        Color { r, g, b }
     }
 
     pub fn black() -> Color {
@@ -52,9 +52,48 @@ impl Color {
         }
     }
 
+    // A color picked in an image editor or a color picker, which give sRGB values from 0 to 255.
+    pub fn from_srgb8(r: u8, g: u8, b: u8) -> Color {
+        Color::new(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0).to_linear()
+    }
+
+    // Decodes sRGB values from 0 to 1 into linear ones.
+    pub fn to_linear(&self) -> Color {
+        Color::new(srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b))
+    }
+
+    // Encodes the linear color as sRGB, which is what screens and image files expect.
+    pub fn to_srgb(&self) -> Color {
+        Color::new(linear_to_srgb(self.r), linear_to_srgb(self.g), linear_to_srgb(self.b))
+    }
+
+    // A rough approximation of `to_srgb`, with a gamma of 2.
     pub fn gamma2(&self) -> Color {
         Color::new(self.r.sqrt(), self.g.sqrt(), self.b.sqrt())
     }
+
+    // The perceived brightness of the color, with the weights of Rec. 709.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+}
+
+// The sRGB transfer function, a straight line near black and a gamma of 2.4 above it, see IEC
+// 61966-2-1. Negative values stay negative.
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.003_130_8 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
 }
 
 impl Mul<Color> for Color {
@@ -96,3 +135,19 @@ impl Add<Color> for Color {
         Color::new(self.r + color.r, self.g + color.g, self.b + color.b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use super::{Color, linear_to_srgb, srgb_to_linear};
+
+    #[test]
+    fn srgb_should_decode_back_to_the_same_linear_color() {
+        for &value in [0.0, 0.001, 0.003_130_8, 0.2, 0.5, 1.0].iter() {
+            assert_that!(srgb_to_linear(linear_to_srgb(value)), is(close_to(value, 1e-12)));
+        }
+        assert_that!(linear_to_srgb(0.5), is(close_to(0.735_357, 1e-6)));
+        assert_that!(Color::from_srgb8(255, 128, 0).g, is(close_to(0.215_861, 1e-5)));
+        assert_that!(Color::white().to_srgb().r, is(close_to(1.0, 1e-12)));
+    }
+}
@@ -0,0 +1,228 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use aabb::Aabb;
+use material::{self, Material};
+use scene::Intersection;
+use prelude::*;
+
+// An axis-aligned box, between `center - half_size` and `center + half_size`. A box that faces
+// another way is a `Transformed<Cuboid>`, see `rotated`.
+#[derive(Clone)]
+pub struct Cuboid {
+    pub center: Vec3,
+    pub half_size: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cuboid {
+    // The axis-aligned box between two opposite corners.
+    pub fn new(a: Vec3, b: Vec3, color: Color) -> Cuboid {
+        Cuboid::with_material(a, b, material::lambertian(color))
+    }
+
+    pub fn reflective(a: Vec3, b: Vec3, color: Color, diffusiveness: f64) -> Cuboid {
+        Cuboid::with_material(a, b, material::metal(color, diffusiveness))
+    }
+
+    pub fn refractive(a: Vec3, b: Vec3, refraction_index: f64) -> Cuboid {
+        Cuboid::with_material(a, b, material::dielectric(refraction_index))
+    }
+
+    pub fn with_material(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> Cuboid {
+        let size = b - a;
+        Cuboid {
+            center: (a + b) / 2.0,
+            half_size: Vec3::new(size.x.abs(), size.y.abs(), size.z.abs()) / 2.0,
+            material,
+        }
+    }
+
+    // Turns the box around its center, e.g. with `Matrix4::rot(Axis::Y, angle)`, a scale or shear
+    // stretches it too. `None` if the transform can not be undone, like a scale by 0.
+    pub fn rotated(&self, rotation: Matrix4) -> Option<Transformed<Cuboid>> {
+        let center = self.center;
+        Transformed::new(self.clone(),
+                         Matrix4::translate(center) * rotation * Matrix4::translate(-1.0 * center))
+    }
+
+    // How far across the box the point is along the axis, from 0 to 1.
+    fn across_face(&self, point: Vec3, axis: u32) -> f64 {
+        if self.half_size[axis] > 0.0 {
+            (point[axis] / self.half_size[axis] + 1.0) / 2.0
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Intersectable for Cuboid {
+    // Intersects the ray with the three pairs of planes of the faces (slabs), relative to the
+    // center of the box. The texture coordinates go from 0 to 1 across every face.
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let origin = ray.origin - self.center;
+        let direction = ray.direction;
+
+        let (mut t_near, mut t_far) = (f64::NEG_INFINITY, f64::INFINITY);
+        for axis in 0..3 {
+            let t0 = (-self.half_size[axis] - origin[axis]) / direction[axis];
+            let t1 = (self.half_size[axis] - origin[axis]) / direction[axis];
+            // A ray along the slab that starts outside of it gives NaN, and misses.
+            if t0.is_nan() || t1.is_nan() {
+                return None;
+            }
+            t_near = t_near.max(t0.min(t1));
+            t_far = t_far.min(t0.max(t1));
+        }
+        if t_near > t_far {
+            return None;
+        }
+        // Rays that start inside the box leave it through the far face.
+        let delta = if t_near > t_min { t_near } else { t_far };
+        if delta <= t_min || delta >= t_max {
+            return None;
+        }
+
+        // The face is the one the point is closest to. A flat box, with a half size of 0 along an
+        // axis, is hit on its two faces at right angles to that axis.
+        let point = origin + delta * direction;
+        let distance_to_face = |axis: u32| (self.half_size[axis] - point[axis].abs()).abs();
+        let axis = (0..3u32)
+            .min_by(|&a, &b| {
+                distance_to_face(a).partial_cmp(&distance_to_face(b)).unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        let sign = if point[axis] < 0.0 { -1.0 } else { 1.0 };
+        let mut normal = [0.0; 3];
+        normal[axis as usize] = sign;
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let uv = (self.across_face(point, u_axis), self.across_face(point, v_axis));
+
+        Some(Intersection::new(delta,
+                               ray.point_along_direction(delta),
+                               Vec3::new(normal[0], normal[1], normal[2]),
+                               Box::new(self.clone()))
+            .with_uv(uv))
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        Box::new(Cuboid { center: vec, ..self.clone() })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.center - self.half_size, self.center + self.half_size))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("cuboid");
+        hasher.write_vec3(self.center);
+        hasher.write_vec3(self.half_size);
+        self.material.hash(hasher);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    fn cuboid() -> Cuboid {
+        Cuboid::new(Vec3::new(-1.0, -0.5, -3.0), Vec3::new(1.0, 0.5, -1.0), Color::white())
+    }
+
+    #[test]
+    fn ray_should_hit_the_front_face_of_a_box() {
+        let ray = Ray::new(Vec3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = cuboid().intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(1.0)));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 0.0, 1.0))));
+        assert_that!(i.uv, is(equal_to(Some((0.75, 0.5)))));
+    }
+
+    #[test]
+    fn ray_from_inside_should_hit_the_far_face() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 1.0, 0.0));
+
+        let i = cuboid().intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(0.5)));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+    }
+
+    #[test]
+    fn ray_should_miss_beside_a_box_and_along_its_faces() {
+        let beside = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let along = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        assert_that!(cuboid().intersects(&beside, 0.0, 1000.0).is_none(), is(true));
+        assert_that!(cuboid().intersects(&along, 0.0, 1000.0).is_none(), is(true));
+    }
+
+    #[test]
+    fn rotated_box_should_turn_its_faces_and_bounds() {
+        let rotated = Cuboid::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), Color::white())
+            .rotated(Matrix4::rot(Axis::Y, PI / 4.0))
+            .unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = rotated.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        // The ray hits the edge between two faces, a corner of the box is 2^0.5 from its center.
+        assert_that!(i.distance, is(close_to(5.0 - 2f64.sqrt(), 1e-9)));
+        assert_that!(i.normal.y, is(equal_to(0.0)));
+        assert_that!(i.normal.z, is(close_to(0.5f64.sqrt(), 1e-9)));
+        let aabb = rotated.aabb().unwrap();
+        assert_that!(aabb.max.x, is(close_to(2f64.sqrt(), 1e-9)));
+        assert_that!(aabb.max.y, is(close_to(1.0, 1e-9)));
+    }
+
+    #[test]
+    fn stretched_box_should_be_hit_where_it_was_stretched_to() {
+        // A unit cube stretched to twice its width, then turned a quarter around z: 4 high.
+        let stretched = Cuboid::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.5, 0.5, 0.5), Color::white())
+            .rotated(Matrix4::rot(Axis::Z, PI / 2.0) * Matrix4::scale(Vec3::new(4.0, 1.0, 1.0)))
+            .unwrap();
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let beside = Ray::new(Vec3::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        let i = stretched.intersects(&down, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(3.0, 1e-9)));
+        assert_that!(i.normal.y, is(close_to(1.0, 1e-9)));
+        assert_that!(stretched.intersects(&beside, 0.0, 1000.0).is_none(), is(true));
+        assert_that!(stretched.aabb().unwrap().max.y, is(close_to(2.0, 1e-9)));
+    }
+
+    #[test]
+    fn box_should_turn_around_its_own_center() {
+        let turned = cuboid().rotated(Matrix4::rot(Axis::Y, PI / 2.0)).unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = turned.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        // A quarter turn swaps the width of 2 and the depth of 2 around the center at z = -2.
+        assert_that!(i.distance, is(close_to(1.0, 1e-9)));
+        assert_that!(i.normal.z, is(close_to(1.0, 1e-9)));
+        assert_that!(turned.aabb().unwrap().centroid().z, is(close_to(-2.0, 1e-9)));
+        assert_that!(cuboid().rotated(Matrix4::scale(Vec3::new(0.0, 1.0, 1.0))).is_none(), is(true));
+    }
+
+    #[test]
+    fn flat_box_should_be_hit_on_its_flat_side() {
+        let flat = Cuboid::new(Vec3::new(-1.0, 0.0, -2.0), Vec3::new(1.0, 0.0, -1.0), Color::white());
+        let down = Ray::new(Vec3::new(0.5, 1.0, -1.5), Vec3::new(0.0, -1.0, 0.0));
+
+        let i = flat.intersects(&down, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(1.0)));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+        assert_that!(i.uv, is(equal_to(Some((0.5, 0.75)))));
+    }
+}
@@ -0,0 +1,506 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use aabb::Aabb;
+use material::{self, Material};
+use scene::Intersection;
+use solve::solve_quadratic;
+use prelude::*;
+
+// A tube of `radius` around the line from `base` to `top`, closed by a disk at both ends unless
+// it is `open`. The texture coordinates go around the tube (u) and from the base to the top (v).
+#[derive(Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub top: Vec3,
+    pub radius: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f64, color: Color) -> Cylinder {
+        Cylinder::with_material(base, top, radius, material::lambertian(color))
+    }
+
+    pub fn reflective(base: Vec3, top: Vec3, radius: f64, color: Color, diffusiveness: f64) -> Cylinder {
+        Cylinder::with_material(base, top, radius, material::metal(color, diffusiveness))
+    }
+
+    pub fn refractive(base: Vec3, top: Vec3, radius: f64, refraction_index: f64) -> Cylinder {
+        Cylinder::with_material(base, top, radius, material::dielectric(refraction_index))
+    }
+
+    pub fn with_material(base: Vec3, top: Vec3, radius: f64, material: Arc<dyn Material>) -> Cylinder {
+        Cylinder {
+            base,
+            top,
+            radius,
+            capped: true,
+            material,
+        }
+    }
+
+    // Without the disks at the ends, like a pipe.
+    pub fn open(&self) -> Cylinder {
+        Cylinder { capped: false, ..self.clone() }
+    }
+}
+
+impl Intersectable for Cylinder {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let frame = Frame::new(self.base, self.top);
+        let (o, d) = (frame.point(ray.origin), frame.direction(ray.direction));
+        let r = self.radius;
+        let mut closest = Closest::new(t_min, t_max);
+        // x² + y² = r² between the ends. Without a height there is no side, and both ends are the
+        // same disk, facing along the axis.
+        if frame.height > 0.0 {
+            for t in roots(d.x * d.x + d.y * d.y, o.x * d.x + o.y * d.y, o.x * o.x + o.y * o.y - r * r) {
+                let p = o + t * d;
+                if p.z >= 0.0 && p.z <= frame.height {
+                    closest.add(t, Vec3::new(p.x, p.y, 0.0), (angle(p), p.z / frame.height));
+                }
+            }
+        }
+        if self.capped {
+            if frame.height > 0.0 {
+                add_cap(&mut closest, o, d, 0.0, -1.0, r);
+            }
+            add_cap(&mut closest, o, d, frame.height, 1.0, r);
+        }
+        closest.intersection(self, ray, &frame)
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        let offset = vec - (self.base + self.top) / 2.0;
+        Box::new(Cylinder {
+            base: self.base + offset,
+            top: self.top + offset,
+            ..self.clone()
+        })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let axis = Frame::new(self.base, self.top).axis;
+        Some(disk_aabb(self.base, axis, self.radius).union(&disk_aabb(self.top, axis, self.radius)))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("cylinder");
+        hasher.write_vec3(self.base);
+        hasher.write_vec3(self.top);
+        hasher.write_f64(self.radius);
+        hasher.write_u64(self.capped as u64);
+        self.material.hash(hasher);
+    }
+}
+
+// A cone with a disk of `radius` at `base`, narrowing to a point at `apex`. Closed at the base
+// unless it is `open`, with texture coordinates like a cylinder.
+#[derive(Clone)]
+pub struct Cone {
+    pub base: Vec3,
+    pub apex: Vec3,
+    pub radius: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f64, color: Color) -> Cone {
+        Cone::with_material(base, apex, radius, material::lambertian(color))
+    }
+
+    pub fn with_material(base: Vec3, apex: Vec3, radius: f64, material: Arc<dyn Material>) -> Cone {
+        Cone {
+            base,
+            apex,
+            radius,
+            capped: true,
+            material,
+        }
+    }
+
+    // Without the disk at the base, like a funnel.
+    pub fn open(&self) -> Cone {
+        Cone { capped: false, ..self.clone() }
+    }
+}
+
+impl Intersectable for Cone {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let frame = Frame::new(self.base, self.apex);
+        let (o, d) = (frame.point(ray.origin), frame.direction(ray.direction));
+        let h = frame.height;
+        let mut closest = Closest::new(t_min, t_max);
+        // x² + y² = (k (h - z))², where k is how much the radius shrinks per unit of height.
+        // Without a height there is no side, only the base.
+        if h > 0.0 {
+            let k = self.radius / h;
+            let kk = k * k;
+            let w = h - o.z;
+            for t in roots(d.x * d.x + d.y * d.y - kk * d.z * d.z,
+                           o.x * d.x + o.y * d.y + kk * w * d.z,
+                           o.x * o.x + o.y * o.y - kk * w * w) {
+                let p = o + t * d;
+                // The other half of the double cone, beyond the apex, is not part of it.
+                if p.z >= 0.0 && p.z <= h {
+                    // The side has no normal at the apex, where it comes to a point. The axis
+                    // points away from the cone there.
+                    let normal = Vec3::new(p.x, p.y, kk * (h - p.z));
+                    let normal = if normal.length() < 1e-12 { Vec3::new(0.0, 0.0, 1.0) } else { normal };
+                    closest.add(t, normal, (angle(p), p.z / h));
+                }
+            }
+        }
+        if self.capped {
+            add_cap(&mut closest, o, d, 0.0, -1.0, self.radius);
+        }
+        closest.intersection(self, ray, &frame)
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        let offset = vec - (self.base + self.apex) / 2.0;
+        Box::new(Cone {
+            base: self.base + offset,
+            apex: self.apex + offset,
+            ..self.clone()
+        })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(disk_aabb(self.base, Frame::new(self.base, self.apex).axis, self.radius).grow(self.apex))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("cone");
+        hasher.write_vec3(self.base);
+        hasher.write_vec3(self.apex);
+        hasher.write_f64(self.radius);
+        hasher.write_u64(self.capped as u64);
+        self.material.hash(hasher);
+    }
+}
+
+// All points within `radius` of the line from `a` to `b`: a cylinder with a half sphere on either
+// end. The texture coordinates go around it (u) and from end to end (v).
+#[derive(Clone)]
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl Capsule {
+    pub fn new(a: Vec3, b: Vec3, radius: f64, color: Color) -> Capsule {
+        Capsule::with_material(a, b, radius, material::lambertian(color))
+    }
+
+    pub fn with_material(a: Vec3, b: Vec3, radius: f64, material: Arc<dyn Material>) -> Capsule {
+        Capsule { a, b, radius, material }
+    }
+}
+
+impl Intersectable for Capsule {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let frame = Frame::new(self.a, self.b);
+        let (o, d) = (frame.point(ray.origin), frame.direction(ray.direction));
+        let (r, h) = (self.radius, frame.height);
+        let v = |z: f64| (z + r) / (h + 2.0 * r);
+        let mut closest = Closest::new(t_min, t_max);
+        if h > 0.0 {
+            for t in roots(d.x * d.x + d.y * d.y, o.x * d.x + o.y * d.y, o.x * o.x + o.y * o.y - r * r) {
+                let p = o + t * d;
+                if p.z >= 0.0 && p.z <= h {
+                    closest.add(t, Vec3::new(p.x, p.y, 0.0), (angle(p), v(p.z)));
+                }
+            }
+        }
+        // The half spheres, each only beyond its end of the cylinder. With both ends at the same
+        // point the capsule is a sphere, and there is no cylinder to be beyond.
+        let ends: &[(f64, f64)] = if h > 0.0 { &[(0.0, -1.0), (h, 1.0)] } else { &[(0.0, 0.0)] };
+        for &(z, outwards) in ends.iter() {
+            let center = Vec3::new(0.0, 0.0, z);
+            let oc = o - center;
+            for t in roots(d.dot(d), oc.dot(d), oc.dot(oc) - r * r) {
+                let p = o + t * d;
+                if (p.z - z) * outwards >= 0.0 {
+                    closest.add(t, p - center, (angle(p), v(p.z)));
+                }
+            }
+        }
+        closest.intersection(self, ray, &frame)
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        let offset = vec - (self.a + self.b) / 2.0;
+        Box::new(Capsule {
+            a: self.a + offset,
+            b: self.b + offset,
+            ..self.clone()
+        })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.a - extent, self.a + extent).union(&Aabb::new(self.b - extent, self.b + extent)))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("capsule");
+        hasher.write_vec3(self.a);
+        hasher.write_vec3(self.b);
+        hasher.write_f64(self.radius);
+        self.material.hash(hasher);
+    }
+}
+
+// The axis of a shape and two directions at right angles to it, so the shape can be intersected
+// as if its axis was the z axis, starting at the origin.
+struct Frame {
+    origin: Vec3,
+    axis: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    height: f64,
+}
+
+impl Frame {
+    // A shape with both ends at the same point has no axis of its own, it gets the z axis.
+    fn new(from: Vec3, to: Vec3) -> Frame {
+        let height = (to - from).length();
+        let axis = if height > 0.0 { (to - from) / height } else { Vec3::new(0.0, 0.0, 1.0) };
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        Frame { origin: from, axis, tangent, bitangent, height }
+    }
+
+    fn point(&self, point: Vec3) -> Vec3 {
+        self.direction(point - self.origin)
+    }
+
+    fn direction(&self, direction: Vec3) -> Vec3 {
+        Vec3::new(direction.dot(self.tangent), direction.dot(self.bitangent), direction.dot(self.axis))
+    }
+
+    fn to_world(&self, direction: Vec3) -> Vec3 {
+        direction.x * self.tangent + direction.y * self.bitangent + direction.z * self.axis
+    }
+}
+
+// Keeps the closest of the hits along a ray, with its normal (in the frame of the shape, not
+// normalized) and texture coordinates.
+struct Closest {
+    t_min: f64,
+    t_max: f64,
+    hit: Option<(f64, Vec3, (f64, f64))>,
+}
+
+impl Closest {
+    fn new(t_min: f64, t_max: f64) -> Closest {
+        Closest { t_min, t_max, hit: None }
+    }
+
+    fn add(&mut self, t: f64, normal: Vec3, uv: (f64, f64)) {
+        if t > self.t_min && t < self.t_max {
+            self.t_max = t;
+            self.hit = Some((t, normal, uv));
+        }
+    }
+
+    fn intersection<S: Intersectable + Clone + 'static>(&self,
+                                                        shape: &S,
+                                                        ray: &Ray,
+                                                        frame: &Frame)
+                                                        -> Option<Intersection> {
+        let (t, normal, uv) = self.hit?;
+        Some(Intersection::new(t,
+                               ray.point_along_direction(t),
+                               frame.to_world(normal).normalize(),
+                               Box::new(shape.clone()))
+            .with_uv(uv))
+    }
+}
+
+// The disk at height `z` that closes a tube, facing along the axis if `outwards` is 1 and against
+// it if it is -1.
+fn add_cap(closest: &mut Closest, o: Vec3, d: Vec3, z: f64, outwards: f64, radius: f64) {
+    if d.z == 0.0 {
+        return;
+    }
+    let t = (z - o.z) / d.z;
+    let p = o + t * d;
+    let distance = (p.x * p.x + p.y * p.y).sqrt();
+    if distance <= radius {
+        closest.add(t, Vec3::new(0.0, 0.0, outwards), (angle(p), distance / radius));
+    }
+}
+
+// The solutions of a t² + 2 b t + c = 0, the nearest first. With a = 0 there is just the one of
+// 2 b t + c = 0, like for a ray parallel to the side of a cone.
+fn roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    solve_quadratic(a, 2.0 * b, c)
+}
+
+// How far around the axis the point is, from 0 to 1.
+fn angle(p: Vec3) -> f64 {
+    let angle = p.y.atan2(p.x) / (2.0 * PI);
+    if angle < 0.0 { angle + 1.0 } else { angle }
+}
+
+// The bounds of the disk of `radius` at `center`, at right angles to the unit vector `n`.
+fn disk_aabb(center: Vec3, n: Vec3, radius: f64) -> Aabb {
+    let extent = radius *
+                 Vec3::new((1.0 - n.x * n.x).max(0.0).sqrt(),
+                           (1.0 - n.y * n.y).max(0.0).sqrt(),
+                           (1.0 - n.z * n.z).max(0.0).sqrt());
+    Aabb::new(center - extent, center + extent)
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use tests::down;
+
+    fn cylinder() -> Cylinder {
+        Cylinder::new(Vec3::new(0.0, -1.0, -3.0), Vec3::new(0.0, 1.0, -3.0), 0.5, Color::white())
+    }
+
+    fn towards_z(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn ray_should_hit_the_side_of_a_cylinder_within_its_height() {
+        let i = cylinder().intersects(&towards_z(0.0, 0.5), 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(2.5, 1e-12)));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 0.0, 1.0))));
+        let (_, v) = i.uv.unwrap();
+        assert_that!(v, is(close_to(0.75, 1e-12)));
+        assert_that!(cylinder().intersects(&towards_z(0.0, 1.5), 0.0, 1000.0).is_none(), is(true));
+    }
+
+    #[test]
+    fn caps_should_close_a_cylinder_unless_it_is_open() {
+        let cap = cylinder().intersects(&down(0.1, -3.0), 0.0, 1000.0).unwrap();
+        let inside = cylinder().open().intersects(&down(0.1, -3.0), 0.0, 1000.0);
+
+        assert_that!(cap.distance, is(close_to(4.0, 1e-12)));
+        assert_that!(cap.normal, is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+        assert_that!(inside.is_none(), is(true));
+    }
+
+    #[test]
+    fn ray_should_hit_a_cone_closer_to_its_apex() {
+        let cone = Cone::new(Vec3::new(0.0, -1.0, -3.0), Vec3::new(0.0, 1.0, -3.0), 1.0, Color::white());
+
+        let middle = cone.intersects(&towards_z(0.0, 0.0), 0.0, 1000.0).unwrap();
+        let up = Ray::new(Vec3::new(0.0, -5.0, -3.0), Vec3::new(0.0, 1.0, 0.0));
+        let base = cone.intersects(&up, 0.0, 1000.0).unwrap();
+
+        // Halfway up the radius is 0.5, and the side leans back by 1 for every 2 up.
+        assert_that!(middle.distance, is(close_to(2.5, 1e-12)));
+        assert_that!(middle.normal.z, is(close_to(2.0 / 5f64.sqrt(), 1e-12)));
+        assert_that!(middle.normal.y, is(close_to(1.0 / 5f64.sqrt(), 1e-12)));
+        assert_that!(base.distance, is(close_to(4.0, 1e-12)));
+        assert_that!(base.normal, is(equal_to(Vec3::new(0.0, -1.0, 0.0))));
+        assert_that!(cone.intersects(&towards_z(0.0, 1.5), 0.0, 1000.0).is_none(), is(true));
+        // Without the base, the ray goes up through the inside of the cone to its apex, which
+        // faces along the axis.
+        let apex = cone.open().intersects(&up, 0.0, 1000.0).unwrap();
+        assert_that!(apex.distance, is(close_to(6.0, 1e-9)));
+        assert_that!(apex.normal, is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+    }
+
+    #[test]
+    fn ray_parallel_to_the_side_of_a_cone_should_hit_it_once() {
+        let cone = Cone::new(Vec3::new(0.0, -1.0, -3.0), Vec3::new(0.0, 1.0, -3.0), 1.0, Color::white());
+        // Along the side that leans back by 1 for every 2 up, through the axis to the other side.
+        let ray = Ray::new(Vec3::new(0.0, -2.0, -2.5), Vec3::new(0.0, 2.0, -1.0));
+
+        let i = cone.open().intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(1.0, 1e-12)));
+        assert_that!(i.intersection_point.z, is(close_to(-3.5, 1e-12)));
+        assert_that!(i.normal.z, is(close_to(-2.0 / 5f64.sqrt(), 1e-12)));
+        assert_that!(i.normal.y, is(close_to(1.0 / 5f64.sqrt(), 1e-12)));
+    }
+
+    #[test]
+    fn ray_should_hit_the_rounded_ends_of_a_capsule() {
+        let capsule = Capsule::new(Vec3::new(-1.0, 0.0, -3.0), Vec3::new(1.0, 0.0, -3.0), 0.5,
+                                   Color::white());
+
+        let side = capsule.intersects(&towards_z(0.5, 0.0), 0.0, 1000.0).unwrap();
+        let end = capsule.intersects(&towards_z(1.3, 0.0), 0.0, 1000.0).unwrap();
+        let along = Ray::new(Vec3::new(5.0, 0.0, -3.0), Vec3::new(-1.0, 0.0, 0.0));
+        let tip = capsule.intersects(&along, 0.0, 1000.0);
+
+        assert_that!(side.distance, is(close_to(2.5, 1e-12)));
+        assert_that!(end.distance, is(close_to(3.0 - 0.4, 1e-12)));
+        assert_that!(end.normal.x, is(close_to(0.6, 1e-12)));
+        assert_that!(tip.unwrap().distance, is(close_to(3.5, 1e-12)));
+        assert_that!(capsule.intersects(&towards_z(1.6, 0.0), 0.0, 1000.0).is_none(), is(true));
+        let aabb = capsule.aabb().unwrap();
+        assert_that!(aabb.min, is(equal_to(Vec3::new(-1.5, -0.5, -3.5))));
+    }
+
+    #[test]
+    fn tilted_cylinder_should_be_bounded_by_its_end_disks() {
+        let cylinder = Cylinder::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), 0.5, Color::white());
+
+        let aabb = cylinder.aabb().unwrap();
+
+        assert_that!(aabb.max.x, is(close_to(1.0 + 0.5 * 0.5f64.sqrt(), 1e-12)));
+        assert_that!(aabb.max.z, is(close_to(0.5, 1e-12)));
+    }
+
+    #[test]
+    fn capsule_with_both_ends_at_one_point_should_be_a_sphere() {
+        let center = Vec3::new(0.0, 0.0, -3.0);
+        let capsule = Capsule::new(center, center, 0.5, Color::white());
+        let sphere = Sphere::new(center, 0.5, Color::white());
+
+        for &x in [0.0, 0.3, 0.49].iter() {
+            let i = capsule.intersects(&towards_z(x, 0.0), 0.0, 1000.0).unwrap();
+            let expected = sphere.intersects(&towards_z(x, 0.0), 0.0, 1000.0).unwrap();
+            assert_that!(i.distance, is(close_to(expected.distance, 1e-12)));
+            assert_that!(i.normal.x, is(close_to(expected.normal.x, 1e-12)));
+            assert_that!(i.normal.z, is(close_to(expected.normal.z, 1e-12)));
+        }
+        assert_that!(capsule.intersects(&towards_z(0.6, 0.0), 0.0, 1000.0).is_none(), is(true));
+    }
+
+    #[test]
+    fn cylinder_and_cone_without_height_should_be_disks() {
+        let center = Vec3::new(0.0, 0.0, -3.0);
+        let cylinder = Cylinder::new(center, center, 0.5, Color::white());
+        let cone = Cone::new(center, center, 0.5, Color::white());
+
+        for shape in [&cylinder as &dyn Intersectable, &cone].iter() {
+            let i = shape.intersects(&towards_z(0.25, 0.0), 0.0, 1000.0).unwrap();
+            assert_that!(i.distance, is(equal_to(3.0)));
+            assert_that!(i.normal.z.abs(), is(equal_to(1.0)));
+            assert_that!(i.uv.unwrap().1, is(equal_to(0.5)));
+            assert_that!(shape.intersects(&towards_z(0.6, 0.0), 0.0, 1000.0).is_none(), is(true));
+            let aabb = shape.aabb().unwrap();
+            assert_that!(aabb.min, is(equal_to(Vec3::new(-0.5, -0.5, -3.0))));
+            assert_that!(aabb.max, is(equal_to(Vec3::new(0.5, 0.5, -3.0))));
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::f64;
+
+use prelude::*;
+
+// Smooths away the noise of an image with few samples per pixel, using the edge-avoiding à-trous
+// wavelet transform of "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination
+// Filtering" (Dammertz et al. 2010). Every iteration blurs every pixel with its neighbours at twice
+// the distance of the iteration before, but a neighbour only counts as much as its color, normal
+// and depth are like those of the pixel, so edges and the outlines of shapes stay sharp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DenoiseSettings {
+    // The number of iterations, 5 reach pixels 31 pixels away.
+    pub iterations: u32,
+    // How different two colors (relative to their brightness), normals and depths (relative to the
+    // depth of the pixel) can be before their pixels stop being blurred together. Smaller keeps
+    // more detail, and more noise.
+    pub color_sigma: f64,
+    pub normal_sigma: f64,
+    pub depth_sigma: f64,
+}
+
+impl DenoiseSettings {
+    pub fn new() -> DenoiseSettings {
+        DenoiseSettings {
+            iterations: 5,
+            color_sigma: 2.0,
+            normal_sigma: 0.2,
+            depth_sigma: 0.1,
+        }
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> DenoiseSettings {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_color_sigma(mut self, color_sigma: f64) -> DenoiseSettings {
+        self.color_sigma = color_sigma;
+        self
+    }
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> DenoiseSettings {
+        DenoiseSettings::new()
+    }
+}
+
+// The radiance of the film with the noise filtered out, like `film.radiance()`. The normal, albedo
+// and depth of a film made `with_aovs` guide the filter: the radiance is divided by the albedo
+// first, so the filter only blurs the lighting and textures stay sharp. Without them, only the
+// colors decide where the edges are.
+pub fn denoise(film: &Film, settings: &DenoiseSettings) -> Vec<Color> {
+    let radiance = film.radiance();
+    let guides = if film.has_aovs() { Some(Guides::new(film)) } else { None };
+    let albedo: Vec<Color> = match guides {
+        Some(ref guides) => guides.albedo.clone(),
+        None => vec![Color::white(); radiance.len()],
+    };
+    let mut colors: Vec<Color> = radiance.iter()
+        .zip(&albedo)
+        .map(|(&color, albedo)| {
+            Color::new(color.r / albedo.r, color.g / albedo.g, color.b / albedo.b)
+        })
+        .collect();
+
+    let (width, height) = (film.width as i64, film.height as i64);
+    for iteration in 0..settings.iterations {
+        let step = 1i64 << iteration;
+        // The colors get smoother with every iteration, so they have to be a little closer to count.
+        let color_sigma = settings.color_sigma / 2f64.sqrt().powi(iteration as i32);
+        let mut filtered = Vec::with_capacity(colors.len());
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                let mut sum = Color::black();
+                let mut total = 0.0;
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let (other_x, other_y) = (x + (dx as i64 - 2) * step, y + (dy as i64 - 2) * step);
+                        if other_x < 0 || other_y < 0 || other_x >= width || other_y >= height {
+                            continue;
+                        }
+                        let other = (other_y * width + other_x) as usize;
+                        let mut weight = kx * ky * gaussian(relative_distance(colors[index], colors[other]),
+                                                            color_sigma);
+                        if let Some(ref guides) = guides {
+                            weight *= guides.weight(index, other, settings);
+                        }
+                        sum = sum + colors[other] * weight;
+                        total += weight;
+                    }
+                }
+                // The pixel itself always has a weight, unless it is NaN.
+                filtered.push(if total > 0.0 { sum / total } else { colors[index] });
+            }
+        }
+        colors = filtered;
+    }
+
+    colors.iter()
+        .zip(&albedo)
+        .map(|(color, albedo)| Color::new(color.r * albedo.r, color.g * albedo.g, color.b * albedo.b))
+        .collect()
+}
+
+// The B3 spline, the weights of the pixels of one row or column of the 5x5 neighbourhood.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Albedos darker than this are not divided by, to keep the radiance of dark surfaces from
+// blowing up.
+const MIN_ALBEDO: f64 = 0.01;
+
+// What the camera rays hit first in every pixel.
+struct Guides {
+    albedo: Vec<Color>,
+    normals: Vec<Vec3>,
+    depths: Vec<f64>,
+}
+
+impl Guides {
+    fn new(film: &Film) -> Guides {
+        let albedo = film.aov_layer(Aov::Albedo)
+            .values
+            .chunks(3)
+            .map(|c| {
+                Color::new((c[0] as f64).max(MIN_ALBEDO),
+                           (c[1] as f64).max(MIN_ALBEDO),
+                           (c[2] as f64).max(MIN_ALBEDO))
+            })
+            .collect();
+        let normals = film.aov_layer(Aov::Normal)
+            .values
+            .chunks(3)
+            .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))
+            .collect();
+        let depths = film.aov_layer(Aov::Depth).values.iter().map(|&depth| depth as f64).collect();
+        Guides { albedo, normals, depths }
+    }
+
+    fn weight(&self, index: usize, other: usize, settings: &DenoiseSettings) -> f64 {
+        let normal = self.normals[index] - self.normals[other];
+        let (depth, other_depth) = (self.depths[index], self.depths[other]);
+        let depth_weight = match (depth.is_finite(), other_depth.is_finite()) {
+            (true, true) => {
+                let difference = (depth - other_depth) / depth.max(other_depth).max(1e-6);
+                gaussian(difference * difference, settings.depth_sigma)
+            }
+            // Two pixels that see the background are alike, a surface and the background are not.
+            (false, false) => 1.0,
+            _ => 0.0,
+        };
+        gaussian(normal.dot(normal), settings.normal_sigma) * depth_weight
+    }
+}
+
+fn gaussian(distance_squared: f64, sigma: f64) -> f64 {
+    (-distance_squared / (sigma * sigma)).exp()
+}
+
+// The squared distance between two colors, relative to their brightness, so bright and dark
+// parts of the image are filtered alike.
+fn relative_distance(a: Color, b: Color) -> f64 {
+    let brightness = 0.5 * (a.luminance() + b.luminance()) + 1e-3;
+    let (r, g, b) = (a.r - b.r, a.g - b.g, a.b - b.b);
+    (r * r + g * g + b * b) / (brightness * brightness)
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::{denoise, DenoiseSettings};
+
+    // A gray image with noise, whose left half faces a different way than its right half.
+    fn noisy_film() -> Film {
+        let mut film = Film::new(16, 8).with_aovs();
+        let mut sampler = RandomSampler::new(3);
+        for y in 0..8 {
+            for x in 0..16 {
+                let brightness = if x < 8 { 0.2 } else { 0.8 };
+                film.add_sample(x, y, Color::white() * (brightness * 2.0 * sampler.next_f64()), 1.0);
+                let mut aov = AovPixel::new();
+                aov.albedo = Color::white();
+                aov.normal = if x < 8 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+                aov.depth = 2.0;
+                aov.hits = 1;
+                film.set_aov(x, y, aov);
+            }
+        }
+        film
+    }
+
+    fn error(pixels: &[Color], x_range: ::std::ops::Range<usize>, expected: f64) -> f64 {
+        let mut error = 0.0;
+        for y in 0..8 {
+            for x in x_range.clone() {
+                let difference = pixels[y * 16 + x].g - expected;
+                error += difference * difference;
+            }
+        }
+        error
+    }
+
+    #[test]
+    fn denoising_should_remove_noise_but_keep_edges_between_normals() {
+        let film = noisy_film();
+
+        let denoised = denoise(&film, &DenoiseSettings::new());
+
+        let noisy = film.radiance();
+        assert_that!(error(&denoised, 0..8, 0.2), is(less_than(error(&noisy, 0..8, 0.2) / 4.0)));
+        assert_that!(error(&denoised, 8..16, 0.8), is(less_than(error(&noisy, 8..16, 0.8) / 4.0)));
+        // The columns next to the edge are not blurred with the other side.
+        let column = |x: usize| (0..8).map(|y| denoised[y * 16 + x].g).sum::<f64>() / 8.0;
+        assert_that!(column(7), is(less_than(0.3)));
+        assert_that!(column(8), is(greater_than(0.6)));
+    }
+
+    #[test]
+    fn denoising_should_leave_a_smooth_image_as_it_is() {
+        let mut film = Film::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                film.add_sample(x, y, Color::new(0.5, 0.25, 1.0), 1.0);
+            }
+        }
+
+        let denoised = denoise(&film, &DenoiseSettings::new());
+
+        for color in denoised {
+            assert_that!(color.g, is(close_to(0.25, 1e-12)));
+        }
+    }
+}
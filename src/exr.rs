@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+
+// A named image with one or more channels, like the normals with the channels X, Y and Z. The
+// layer without a name is the image itself, which viewers show by default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub channels: Vec<&'static str>,
+    // The values of the channels, pixel by pixel and row by row from the top.
+    pub values: Vec<f32>,
+}
+
+impl Layer {
+    pub fn new(name: &str, channels: Vec<&'static str>, values: Vec<f32>) -> Layer {
+        Layer {
+            name: name.to_string(),
+            channels,
+            values,
+        }
+    }
+
+    fn channel_name(&self, channel: &str) -> String {
+        if self.name.is_empty() {
+            channel.to_string()
+        } else {
+            format!("{}.{}", self.name, channel)
+        }
+    }
+}
+
+pub fn save_exr<P: AsRef<Path>>(path: P, width: u32, height: u32, layers: &[Layer]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_exr(&mut writer, width, height, layers)?;
+    writer.flush()
+}
+
+// Writes the layers as one uncompressed OpenEXR image with 32 bit float channels, named
+// "layer.channel" as compositing programs expect, see "The OpenEXR File Layout".
+pub fn write_exr<W: Write>(writer: &mut W, width: u32, height: u32, layers: &[Layer]) -> io::Result<()> {
+    // The channels have to be in alphabetical order, in the header and in every scanline.
+    let mut channels = Vec::new();
+    for layer in layers {
+        assert_eq!(layer.values.len(),
+                   (width * height) as usize * layer.channels.len(),
+                   "layer {:?} should have a value for every channel of every pixel",
+                   layer.name);
+        for (index, channel) in layer.channels.iter().enumerate() {
+            channels.push((layer.channel_name(channel), layer, index));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for (name, _, _) in channels.iter() {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&FLOAT.to_le_bytes());
+        // Not perceptually linear, three reserved bytes, and no subsampling.
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    attribute(&mut header, "channels", "chlist", &channel_list);
+
+    attribute(&mut header, "compression", "compression", &[0]);
+    let mut window = Vec::new();
+    for &value in [0, 0, width as i32 - 1, height as i32 - 1].iter() {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // Every scanline is a block of its own, and the blocks are listed before the first one.
+    let block_size = 8 + 4 * width as u64 * channels.len() as u64;
+    let first_block = header.len() as u64 + 8 * height as u64;
+    for y in 0..height as u64 {
+        writer.write_all(&(first_block + y * block_size).to_le_bytes())?;
+    }
+    for y in 0..height {
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&((block_size - 8) as i32).to_le_bytes())?;
+        for &(_, layer, index) in channels.iter() {
+            let stride = layer.channels.len();
+            for x in 0..width {
+                let value = layer.values[(y * width + x) as usize * stride + index];
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+const FLOAT: i32 = 2;
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use super::{Layer, write_exr};
+
+    fn find(bytes: &[u8], pattern: &[u8]) -> Option<usize> {
+        bytes.windows(pattern.len()).position(|window| window == pattern)
+    }
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        let mut value = [0; 4];
+        value.copy_from_slice(&bytes[offset..offset + 4]);
+        f32::from_le_bytes(value)
+    }
+
+    #[test]
+    fn channels_should_be_sorted_and_named_after_their_layer() {
+        let layers = [Layer::new("", vec!["R", "G", "B"], vec![0.0; 6]),
+                      Layer::new("depth", vec!["Z"], vec![0.0; 2])];
+        let mut bytes = Vec::new();
+
+        write_exr(&mut bytes, 2, 1, &layers).unwrap();
+
+        let names: Vec<usize> = ["B\0", "G\0", "R\0", "depth.Z\0"]
+            .iter()
+            .map(|name| find(&bytes, name.as_bytes()).unwrap())
+            .collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_that!(&bytes[..4], is(equal_to(&[0x76, 0x2f, 0x31, 0x01][..])));
+        assert_that!(names, is(equal_to(sorted)));
+    }
+
+    #[test]
+    fn scanlines_should_hold_the_values_of_every_channel_in_order() {
+        let layers = [Layer::new("", vec!["R", "G", "B"], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+                      Layer::new("depth", vec!["Z"], vec![7.0, 8.0])];
+        let mut bytes = Vec::new();
+
+        write_exr(&mut bytes, 1, 2, &layers).unwrap();
+
+        // The offsets of the two scanlines come right before the first one.
+        let block_size = 8 + 4 * 4;
+        let first = bytes.len() - 2 * block_size;
+        let mut offset = [0; 8];
+        offset.copy_from_slice(&bytes[first - 8..first]);
+        assert_that!(u64::from_le_bytes(offset) as usize, is(equal_to(first + block_size)));
+
+        // The second scanline, with B, G, R and depth.Z.
+        let values: Vec<f32> = (0..4).map(|i| f32_at(&bytes, first + block_size + 8 + 4 * i)).collect();
+        assert_that!(values, is(equal_to(vec![6.0, 5.0, 4.0, 8.0])));
+    }
+}
@@ -0,0 +1,353 @@
+use std::f64;
+
+use aov::{Aov, id_color};
+use exr::Layer;
+use prelude::*;
+
+// Collects the samples of an image over any number of render passes. Every pixel keeps the
+// weighted sum of its samples rather than their average, so the image can be looked at after any
+// pass, and a later pass simply adds more samples to the same film.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pub passes: u32,
+    pixels: Vec<FilmPixel>,
+    // Empty unless the film was made `with_aovs`.
+    aovs: Vec<AovPixel>,
+}
+
+// What a pixel of the film has seen so far.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilmPixel {
+    pub sum: Color,
+    pub weight: f64,
+    pub num_samples: u32,
+    // The running mean and variance of the luminance of the samples, for adaptive sampling.
+    pub luminance: RunningStats,
+}
+
+impl FilmPixel {
+    pub fn new() -> FilmPixel {
+        FilmPixel {
+            sum: Color::black(),
+            weight: 0.0,
+            num_samples: 0,
+            luminance: RunningStats::default(),
+        }
+    }
+
+    pub fn add_sample(&mut self, color: Color, weight: f64) {
+        self.splat(color, weight);
+        self.count_sample(color);
+    }
+
+    // Adds a sample that was taken for a pixel nearby, with the weight of the filter.
+    pub fn splat(&mut self, color: Color, weight: f64) {
+        self.sum = self.sum + color * weight;
+        self.weight += weight;
+    }
+
+    // Counts a sample that was taken for this pixel, whichever pixels it was splatted into.
+    pub fn count_sample(&mut self, color: Color) {
+        self.num_samples += 1;
+        self.luminance.add(color.luminance());
+    }
+
+    pub fn merge(&mut self, other: &FilmPixel) {
+        self.sum = self.sum + other.sum;
+        self.weight += other.weight;
+        self.num_samples += other.num_samples;
+        self.luminance.merge(&other.luminance);
+    }
+
+    // The weighted average of the samples, black until the pixel has a sample. Filters with
+    // negative lobes can make a channel negative next to a bright edge, which is cut off at 0.
+    pub fn color(&self) -> Color {
+        if self.weight > 0.0 {
+            let color = self.sum / self.weight;
+            Color::new(color.r.max(0.0), color.g.max(0.0), color.b.max(0.0))
+        } else {
+            Color::black()
+        }
+    }
+}
+
+impl Default for FilmPixel {
+    fn default() -> FilmPixel {
+        FilmPixel::new()
+    }
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            passes: 0,
+            pixels: vec![FilmPixel::new(); (width * height) as usize],
+            aovs: Vec::new(),
+        }
+    }
+
+    // Also keeps arbitrary output variables (what the camera rays hit first, and the direct and
+    // indirect light) for every pixel, for compositing.
+    pub fn with_aovs(mut self) -> Film {
+        self.aovs = vec![AovPixel::new(); self.pixels.len()];
+        self
+    }
+
+    pub fn has_aovs(&self) -> bool {
+        !self.aovs.is_empty()
+    }
+
+    pub fn aov(&self, x: u32, y: u32) -> AovPixel {
+        self.aovs[(y * self.width + x) as usize]
+    }
+
+    pub fn set_aov(&mut self, x: u32, y: u32, aov: AovPixel) {
+        self.aovs[(y * self.width + x) as usize] = aov;
+    }
+
+    pub fn merge_aov(&mut self, x: u32, y: u32, aov: &AovPixel) {
+        self.aovs[(y * self.width + x) as usize].merge(aov);
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> FilmPixel {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: FilmPixel) {
+        self.pixels[(y * self.width + x) as usize] = pixel;
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, color: Color, weight: f64) {
+        self.pixels[(y * self.width + x) as usize].add_sample(color, weight);
+    }
+
+    pub fn merge_pixel(&mut self, x: u32, y: u32, pixel: &FilmPixel) {
+        self.pixels[(y * self.width + x) as usize].merge(pixel);
+    }
+
+    // The image so far, sRGB encoded like the image of `render`.
+    pub fn image(&self) -> Vec<Color> {
+        self.radiance().iter().map(|color| color.to_srgb()).collect()
+    }
+
+    // The radiance that reached every pixel so far, as `save_image` expects it.
+    pub fn radiance(&self) -> Vec<Color> {
+        self.pixels.iter().map(|pixel| pixel.color()).collect()
+    }
+
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(|pixel| pixel.num_samples).collect()
+    }
+
+    // The radiance as the unnamed layer, followed by a layer for every output variable when the
+    // film has them.
+    pub fn layers(&self) -> Vec<Layer> {
+        let beauty = self.radiance()
+            .iter()
+            .flat_map(|color| vec![color.r as f32, color.g as f32, color.b as f32])
+            .collect();
+        let mut layers = vec![Layer::new("", vec!["R", "G", "B"], beauty)];
+        if self.has_aovs() {
+            layers.extend(Aov::ALL.iter().map(|&aov| self.aov_layer(aov)));
+        }
+        layers
+    }
+
+    // The values of an output variable, averaged like `AovPixel` describes. Pixels that no camera
+    // ray hit have an infinite depth, a position at the origin and -1 as their ids.
+    pub fn aov_layer(&self, aov: Aov) -> Layer {
+        assert!(self.has_aovs() || aov == Aov::SampleCount,
+                "the film should be made with_aovs to keep the {}",
+                aov.name());
+        let mut values = Vec::with_capacity(self.pixels.len() * aov.channels().len());
+        for (index, pixel) in self.pixels.iter().enumerate() {
+            if aov == Aov::SampleCount {
+                values.push(pixel.num_samples as f32);
+                continue;
+            }
+            let aov_pixel = &self.aovs[index];
+            let samples = pixel.num_samples.max(1) as f64;
+            let hits = aov_pixel.hits.max(1) as f64;
+            let id = |id: Option<u32>| id.map_or(-1.0, |id| id as f32);
+            match aov {
+                Aov::Albedo => push_color(&mut values, aov_pixel.albedo / samples),
+                Aov::Normal => push_vec3(&mut values, aov_pixel.normal / samples),
+                Aov::Position => push_vec3(&mut values, aov_pixel.position / hits),
+                Aov::Depth if aov_pixel.hits == 0 => values.push(f32::INFINITY),
+                Aov::Depth => values.push((aov_pixel.depth / hits) as f32),
+                Aov::ShapeId => values.push(id(aov_pixel.shape)),
+                Aov::MaterialId => values.push(id(aov_pixel.material)),
+                Aov::Direct | Aov::Indirect if pixel.weight <= 0.0 => push_color(&mut values, Color::black()),
+                Aov::Direct => push_color(&mut values, aov_pixel.direct / pixel.weight),
+                Aov::Indirect => push_color(&mut values, aov_pixel.indirect / pixel.weight),
+                Aov::SampleCount => unreachable!(),
+            }
+        }
+        Layer::new(aov.name(), aov.channels(), values)
+    }
+
+    // An output variable as an image to look at. The values are the ones to show, so save them with
+    // `save_raw_image`, which does not sRGB encode them again: albedo and light are sRGB encoded
+    // already, normals are mapped from -1..1 to 0..1, positions are scaled to the box around them,
+    // depths fade from white up close, and every id gets a color of its own. Pixels that no camera
+    // ray hit are black.
+    pub fn aov_image(&self, aov: Aov) -> Vec<Color> {
+        let layer = self.aov_layer(aov);
+        let hits: Vec<bool> = self.aovs.iter().map(|aov| aov.hits > 0).collect();
+        let vectors: Vec<Vec3> = layer.values
+            .chunks(3)
+            .map(|v| Vec3::new(v[0] as f64, v[1] as f64, v[2] as f64))
+            .collect();
+        match aov {
+            Aov::SampleCount => sample_heatmap(&self.sample_counts()),
+            Aov::Albedo | Aov::Direct | Aov::Indirect => {
+                vectors.iter().map(|v| Color::new(v.x, v.y, v.z).to_srgb()).collect()
+            }
+            Aov::Normal | Aov::Position => {
+                let (min, max) = if aov == Aov::Normal {
+                    (Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0))
+                } else {
+                    bounds(vectors.iter().zip(&hits).filter(|&(_, &hit)| hit).map(|(&v, _)| v))
+                };
+                let scale = |value: f64, min: f64, max: f64| {
+                    if max > min { (value - min) / (max - min) } else { 1.0 }
+                };
+                vectors.iter()
+                    .zip(&hits)
+                    .map(|(v, &hit)| if hit {
+                        Color::new(scale(v.x, min.x, max.x), scale(v.y, min.y, max.y), scale(v.z, min.z, max.z))
+                    } else {
+                        Color::black()
+                    })
+                    .collect()
+            }
+            Aov::Depth => {
+                let far = layer.values.iter().cloned().filter(|depth| depth.is_finite()).fold(0.0, f32::max);
+                layer.values
+                    .iter()
+                    .map(|&depth| if !depth.is_finite() {
+                        Color::black()
+                    } else if far > 0.0 {
+                        Color::white() * (1.0 - 0.9 * (depth / far) as f64)
+                    } else {
+                        Color::white()
+                    })
+                    .collect()
+            }
+            Aov::ShapeId | Aov::MaterialId => {
+                layer.values
+                    .iter()
+                    .map(|&id| if id < 0.0 { Color::black() } else { id_color(id as u32) })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn push_color(values: &mut Vec<f32>, color: Color) {
+    values.extend_from_slice(&[color.r as f32, color.g as f32, color.b as f32]);
+}
+
+fn push_vec3(values: &mut Vec<f32>, vec: Vec3) {
+    values.extend_from_slice(&[vec.x as f32, vec.y as f32, vec.z as f32]);
+}
+
+// The smallest and largest coordinates of the vectors.
+fn bounds<I: Iterator<Item = Vec3>>(vectors: I) -> (Vec3, Vec3) {
+    let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for vec in vectors {
+        min = Vec3::new(min.x.min(vec.x), min.y.min(vec.y), min.z.min(vec.z));
+        max = Vec3::new(max.x.max(vec.x), max.y.max(vec.y), max.z.max(vec.z));
+    }
+    (min, max)
+}
+
+// The running mean and variance of a series of values (Welford's algorithm).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RunningStats {
+    pub count: u64,
+    pub mean: f64,
+    pub sum_of_squares: f64,
+}
+
+impl RunningStats {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.sum_of_squares += delta * (value - self.mean);
+    }
+
+    // Combines the statistics of two series of values, see "Updating Formulae and a Pairwise
+    // Algorithm for Computing Sample Variances" (Chan et al. 1979).
+    pub fn merge(&mut self, other: &RunningStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let weight = self.count as f64 * other.count as f64 / count as f64;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.sum_of_squares += other.sum_of_squares + delta * delta * weight;
+        self.count = count;
+    }
+
+    pub fn standard_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.sum_of_squares / (self.count - 1) as f64;
+        (variance / self.count as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::RunningStats;
+
+    #[test]
+    fn film_should_average_the_weighted_samples() {
+        let mut film = Film::new(2, 1);
+
+        film.add_sample(1, 0, Color::new(1.0, 0.0, 0.0), 1.0);
+        film.add_sample(1, 0, Color::new(0.0, 0.0, 1.0), 3.0);
+
+        assert_that!(film.pixel(1, 0).color(), is(equal_to(Color::new(0.25, 0.0, 0.75))));
+        assert_that!(film.pixel(0, 0).color(), is(equal_to(Color::black())));
+        assert_that!(film.sample_counts(), is(equal_to(vec![0, 2])));
+    }
+
+    #[test]
+    fn running_stats_should_give_the_mean_and_standard_error() {
+        let mut stats = RunningStats::default();
+        for &value in [1.0, 2.0, 3.0, 4.0].iter() {
+            stats.add(value);
+        }
+
+        assert_that!(stats.mean, is(equal_to(2.5)));
+        assert_that!(stats.standard_error(),
+                     is(close_to((5.0f64 / 3.0 / 4.0).sqrt(), 1e-12)));
+    }
+
+    #[test]
+    fn merged_stats_should_match_the_stats_of_all_values() {
+        let (mut first, mut second) = (RunningStats::default(), RunningStats::default());
+        first.add(1.0);
+        for &value in [2.0, 3.0, 4.0].iter() {
+            second.add(value);
+        }
+
+        first.merge(&second);
+
+        assert_that!(first.count, is(equal_to(4)));
+        assert_that!(first.mean, is(close_to(2.5, 1e-12)));
+        assert_that!(first.sum_of_squares, is(close_to(5.0, 1e-12)));
+    }
+}
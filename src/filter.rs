@@ -0,0 +1,123 @@
+use std::f64::consts::PI;
+
+// Decides how much a sample counts towards the pixels around it. Every sample is spread over the
+// pixels whose centers are within `radius` of it (in both directions), weighted by the filter, and
+// a pixel is the weighted average of the samples it gets. The box filter of radius 0.5 gives every
+// sample to its own pixel only, wider filters blur a little but give smoother edges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, alpha: f64 },
+    // "Reconstruction Filters in Computer Graphics" (Mitchell and Netravali 1988).
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // A sinc windowed by a wider sinc, `tau` is the number of lobes.
+    Lanczos { radius: f64, tau: f64 },
+}
+
+impl Filter {
+    // The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 5] = ["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    // The filter with its usual parameters.
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "box" => Some(Filter::Box { radius: 0.5 }),
+            "tent" => Some(Filter::Tent { radius: 1.0 }),
+            "gaussian" => Some(Filter::Gaussian { radius: 1.5, alpha: 2.0 }),
+            "mitchell" => {
+                Some(Filter::Mitchell {
+                    radius: 2.0,
+                    b: 1.0 / 3.0,
+                    c: 1.0 / 3.0,
+                })
+            }
+            "lanczos" => Some(Filter::Lanczos { radius: 3.0, tau: 3.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius } |
+            Filter::Tent { radius } |
+            Filter::Gaussian { radius, .. } |
+            Filter::Mitchell { radius, .. } |
+            Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    // The weight of a sample at offset (x, y) from the center of a pixel. Mitchell and Lanczos
+    // have negative lobes, which sharpen the image.
+    pub fn weight(&self, x: f64, y: f64) -> f64 {
+        self.weight_1d(x) * self.weight_1d(y)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => mitchell(2.0 * x / radius, b, c),
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    if x > 1.0 {
+        ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x +
+         (8.0 * b + 24.0 * c)) / 6.0
+    } else {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x +
+         (6.0 - 2.0 * b)) / 6.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use super::Filter;
+
+    #[test]
+    fn filters_should_weigh_the_center_the_most_and_vanish_beyond_the_radius() {
+        for name in Filter::NAMES.iter() {
+            let filter = Filter::from_name(name).unwrap();
+            let center = filter.weight(0.0, 0.0);
+
+            assert_that!(center, is(greater_than(0.0)));
+            assert_that!(filter.weight(0.3, 0.1), is(less_than_or_equal_to(center)));
+            assert_that!(filter.weight(filter.radius() + 0.01, 0.0), is(equal_to(0.0)));
+            assert_that!(filter.weight(0.3, -0.2), is(equal_to(filter.weight(-0.3, 0.2))));
+        }
+    }
+
+    #[test]
+    fn mitchell_and_lanczos_should_have_negative_lobes() {
+        let mitchell = Filter::from_name("mitchell").unwrap();
+        let lanczos = Filter::from_name("lanczos").unwrap();
+
+        assert_that!(mitchell.weight(1.5, 0.0), is(less_than(0.0)));
+        assert_that!(lanczos.weight(1.5, 0.0), is(less_than(0.0)));
+    }
+}
@@ -0,0 +1,449 @@
+use std::f64;
+use std::fmt;
+
+use scatter;
+use scene::Intersection;
+use prelude::*;
+
+// Computes the light that arrives along a ray, this is where the rendering algorithm lives.
+// `trace_scene_with` takes any integrator, so the algorithm can be picked at runtime.
+pub trait Integrator: Send + Sync + fmt::Debug {
+    // The name of the algorithm, one of `NAMES` for the integrators of this crate. Checkpoints
+    // keep it along with the settings (the `Debug` output), so a render is resumed with the same.
+    fn name(&self) -> &'static str;
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color;
+
+    // The same radiance split in two: the light that reaches the first surface straight from a
+    // light (or that the surface gives off itself), and the light that bounced more than once.
+    // Integrators that do not tell them apart count all of it as direct.
+    fn direct_and_indirect(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> (Color, Color) {
+        (self.radiance(ray, scene, sampler), Color::black())
+    }
+}
+
+// The names accepted by `from_name`.
+pub const NAMES: [&str; 5] = ["path", "whitted", "ao", "normals", "depth"];
+
+// An integrator with its default settings, for picking one on the command line.
+pub fn from_name(name: &str) -> Option<Box<dyn Integrator>> {
+    match name {
+        "path" => Some(Box::new(PathTracer::new())),
+        "whitted" => Some(Box::new(Whitted::new())),
+        "ao" => Some(Box::new(AmbientOcclusion::new(1.0))),
+        "normals" => Some(Box::new(Normals)),
+        "depth" => Some(Box::new(Depth::new(10.0))),
+        _ => None,
+    }
+}
+
+// Follows rays as they scatter around the scene. Diffusive surfaces also send a shadow ray
+// towards a random point on a light, and light that can be found both ways is weighted with the
+// power heuristic of multiple importance sampling (Veach 1997).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathTracer {
+    pub max_depth: u32,
+    // Paths that have bounced this many times are terminated at random, with a probability that
+    // grows as they carry less light. The paths that survive carry more light to make up for it.
+    pub russian_roulette_depth: Option<u32>,
+    pub light_sampling: bool,
+}
+
+impl PathTracer {
+    pub fn new() -> PathTracer {
+        PathTracer {
+            max_depth: 50,
+            russian_roulette_depth: None,
+            light_sampling: true,
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> PathTracer {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_russian_roulette(mut self, depth: u32) -> PathTracer {
+        self.russian_roulette_depth = Some(depth);
+        self
+    }
+
+    pub fn with_light_sampling(mut self, light_sampling: bool) -> PathTracer {
+        self.light_sampling = light_sampling;
+        self
+    }
+
+    // `emission_weight` is the weight of the light given off by the shape `ray` hits.
+    fn trace(&self,
+             ray: &Ray,
+             scene: &Scene,
+             sampler: &mut dyn Sampler,
+             depth: u32,
+             emission_weight: f64)
+             -> PathLight {
+        if depth >= self.max_depth {
+            return PathLight::emitted(Color::black());
+        }
+        let intersection = match scene.intersects(ray, 0.0, f64::MAX) {
+            Some(intersection) => intersection,
+            None => return PathLight::emitted(scene.background.color(ray)),
+        };
+        let emitted = emission_weight * intersection.shape.emitted(ray, &intersection);
+        let (attenuation, scattered) = match intersection.shape.scatter(ray, &intersection, sampler) {
+            Some(scattered) => scattered,
+            None => return PathLight::emitted(emitted),
+        };
+
+        let material = intersection.shape.material();
+        let scattering_pdf = material.map_or(0.0, |material| {
+            material.scattering_pdf(ray, &intersection, scattered.direction)
+        });
+        let (direct, weight) = if self.light_sampling && scene.has_lights() && scattering_pdf > 0.0 {
+            let direct = material.map_or(Color::black(), |material| {
+                sample_direct_light(ray, &intersection, material, scene, sampler, true)
+            });
+            let light_pdf = scene.light_pdf(scattered.origin, scattered.direction);
+            (direct, power_heuristic(scattering_pdf, light_pdf))
+        } else {
+            (Color::black(), 1.0)
+        };
+
+        let attenuation = match self.russian_roulette_depth {
+            Some(russian_roulette_depth) if depth >= russian_roulette_depth => {
+                let survival = attenuation.r.max(attenuation.g).max(attenuation.b).min(1.0);
+                if survival <= 0.0 || sampler.next_f64() >= survival {
+                    return PathLight {
+                        emitted,
+                        direct,
+                        indirect: Color::black(),
+                    };
+                }
+                attenuation / survival
+            }
+            _ => attenuation,
+        };
+        let next = self.trace(&scattered, scene, sampler, depth + 1, weight);
+        PathLight {
+            emitted,
+            direct: direct + attenuation * next.emitted,
+            indirect: attenuation * (next.direct + next.indirect),
+        }
+    }
+}
+
+// The light a path brings back from a surface: what the surface gives off itself, what reaches
+// it straight from a light, and what reaches it after more bounces.
+struct PathLight {
+    emitted: Color,
+    direct: Color,
+    indirect: Color,
+}
+
+impl PathLight {
+    fn emitted(emitted: Color) -> PathLight {
+        PathLight {
+            emitted,
+            direct: Color::black(),
+            indirect: Color::black(),
+        }
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> PathTracer {
+        PathTracer::new()
+    }
+}
+
+impl Integrator for PathTracer {
+    fn name(&self) -> &'static str {
+        "path"
+    }
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let (direct, indirect) = self.direct_and_indirect(ray, scene, sampler);
+        direct + indirect
+    }
+
+    fn direct_and_indirect(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> (Color, Color) {
+        let light = self.trace(ray, scene, sampler, 0, 1.0);
+        (light.emitted + light.direct, light.indirect)
+    }
+}
+
+// Whitted-style ray tracing: mirrors and glass are followed recursively, every other surface
+// only shows the light that reaches it directly from the lights of the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Whitted {
+    pub max_depth: u32,
+}
+
+impl Whitted {
+    pub fn new() -> Whitted {
+        Whitted { max_depth: 50 }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> Whitted {
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn trace(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: u32) -> Color {
+        if depth >= self.max_depth {
+            return Color::black();
+        }
+        let intersection = match scene.intersects(ray, 0.0, f64::MAX) {
+            Some(intersection) => intersection,
+            None => return scene.background.color(ray),
+        };
+        let emitted = intersection.shape.emitted(ray, &intersection);
+        let (attenuation, scattered) = match intersection.shape.scatter(ray, &intersection, sampler) {
+            Some(scattered) => scattered,
+            None => return emitted,
+        };
+        match intersection.shape.material() {
+            Some(material) if material.scattering_pdf(ray, &intersection, scattered.direction) > 0.0 => {
+                emitted + sample_direct_light(ray, &intersection, material, scene, sampler, false)
+            }
+            _ => emitted + attenuation * self.trace(&scattered, scene, sampler, depth + 1),
+        }
+    }
+}
+
+impl Default for Whitted {
+    fn default() -> Whitted {
+        Whitted::new()
+    }
+}
+
+impl Integrator for Whitted {
+    fn name(&self) -> &'static str {
+        "whitted"
+    }
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        self.trace(ray, scene, sampler, 0)
+    }
+}
+
+// White where a random ray from the surface escapes further than `distance`, black where it
+// hits something. Averaged over many samples this shows how exposed each point is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(distance: f64) -> AmbientOcclusion {
+        AmbientOcclusion { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn name(&self) -> &'static str {
+        "ao"
+    }
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let intersection = match scene.intersects(ray, 0.0, f64::MAX) {
+            Some(intersection) => intersection,
+            None => return Color::white(),
+        };
+        let (_, occlusion_ray) = scatter::diffusive(Color::white(), ray, &intersection, sampler)
+            .expect("diffusive scattering always scatters");
+        match scene.intersects(&occlusion_ray, 0.0, self.distance) {
+            Some(_) => Color::black(),
+            None => Color::white(),
+        }
+    }
+}
+
+// Shows the surface normal at the first intersection, mapped from [-1, 1] to [0, 1].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn name(&self) -> &'static str {
+        "normals"
+    }
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, _: &mut dyn Sampler) -> Color {
+        match scene.intersects(ray, 0.0, f64::MAX) {
+            Some(intersection) => {
+                let n = intersection.normal.normalize();
+                Color::new(0.5 * (n.x + 1.0), 0.5 * (n.y + 1.0), 0.5 * (n.z + 1.0))
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+// Shows the distance to the first intersection, white up close and fading to black at
+// `max_distance`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Depth {
+    pub max_distance: f64,
+}
+
+impl Depth {
+    pub fn new(max_distance: f64) -> Depth {
+        Depth { max_distance }
+    }
+}
+
+impl Integrator for Depth {
+    fn name(&self) -> &'static str {
+        "depth"
+    }
+
+    fn radiance(&self, ray: &Ray, scene: &Scene, _: &mut dyn Sampler) -> Color {
+        match scene.intersects(ray, 0.0, f64::MAX) {
+            Some(intersection) => {
+                let distance = intersection.distance * ray.direction.length();
+                let gray = 1.0 - (distance / self.max_distance).min(1.0);
+                Color::new(gray, gray, gray)
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+// The light that reaches `intersection` through a shadow ray towards a random light and
+// scatters along `ray`. With `weighted` it is weighted for multiple importance sampling against
+// `material`.
+fn sample_direct_light(ray: &Ray,
+                       intersection: &Intersection,
+                       material: &dyn Material,
+                       scene: &Scene,
+                       sampler: &mut dyn Sampler,
+                       weighted: bool)
+                       -> Color {
+    let direction = match scene.sample_light(intersection.intersection_point, sampler) {
+        Some(direction) => direction.normalize(),
+        None => return Color::black(),
+    };
+    let shadow_ray = scatter::ray_towards(intersection, direction);
+    let light_pdf = scene.light_pdf(shadow_ray.origin, direction);
+    if light_pdf <= 0.0 {
+        return Color::black();
+    }
+    match scene.intersects(&shadow_ray, 0.0, f64::MAX) {
+        Some(light) => {
+            let radiance = light.shape.emitted(&shadow_ray, &light);
+            let weight = if weighted {
+                power_heuristic(light_pdf, material.scattering_pdf(ray, intersection, direction))
+            } else {
+                1.0
+            };
+            material.eval(ray, intersection, direction) * radiance * (weight / light_pdf)
+        }
+        None => Color::black(),
+    }
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    fn ray_down() -> Ray {
+        Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0))
+    }
+
+    fn floor() -> Sphere {
+        Sphere::new(Vec3::new(0.0, -100.0, 0.0), 100.0, Color::new(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn integrators_should_be_picked_by_name() {
+        for name in ::integrator::NAMES.iter() {
+            assert_that!(::integrator::from_name(name).unwrap().name(), is(equal_to(*name)));
+        }
+        assert_that!(::integrator::from_name("photon mapping").is_none(), is(true));
+    }
+
+    #[test]
+    fn path_tracer_should_stop_at_max_depth() {
+        let scene = Scene::new(vec![Box::new(floor())]);
+        let mut sampler = RandomSampler::new(0);
+
+        let color = PathTracer::new().with_max_depth(0).radiance(&ray_down(), &scene, &mut sampler);
+
+        assert_that!(color, is(equal_to(Color::black())));
+    }
+
+    #[test]
+    fn russian_roulette_should_not_change_the_average() {
+        let scene = Scene::new(vec![Box::new(floor())]).with_background(Background::Color(Color::white()));
+        let integrator = PathTracer::new().with_russian_roulette(0);
+        let num_samples = 20000;
+        let mut sampler = RandomSampler::new(0);
+
+        let sum = (0..num_samples).fold(0.0, |sum, _| {
+            sum + integrator.radiance(&ray_down(), &scene, &mut sampler).r
+        });
+
+        // Half of the light is absorbed at the floor, and every scattered ray reaches the sky.
+        assert_that!((sum / num_samples as f64 - 0.5).abs(), is(less_than(0.02)));
+    }
+
+    #[test]
+    fn direct_and_indirect_light_should_add_up_to_the_radiance() {
+        let light = Sphere::emissive(Vec3::new(0.0, 2.0, 0.0), 0.5, Color::white());
+        let scene = Scene::new(vec![Box::new(floor()), Box::new(light)]);
+        let integrator = PathTracer::new();
+
+        for seed in 0..10 {
+            let radiance = integrator.radiance(&ray_down(), &scene, &mut RandomSampler::new(seed));
+            let (direct, indirect) =
+                integrator.direct_and_indirect(&ray_down(), &scene, &mut RandomSampler::new(seed));
+
+            assert_that!(direct.r, is(greater_than(0.0)));
+            assert_that!(direct + indirect, is(equal_to(radiance)));
+        }
+    }
+
+    #[test]
+    fn whitted_should_only_light_diffusive_surfaces_directly() {
+        let light = Sphere::emissive(Vec3::new(0.0, 2.0, 0.0), 0.5, Color::white());
+        let lit = Scene::new(vec![Box::new(floor()), Box::new(light)]).with_background(Background::black());
+        let dark = Scene::new(vec![Box::new(floor())]).with_background(Background::Color(Color::white()));
+        let mut sampler = RandomSampler::new(0);
+
+        assert_that!(Whitted::new().radiance(&ray_down(), &lit, &mut sampler).r, is(greater_than(0.0)));
+        assert_that!(Whitted::new().radiance(&ray_down(), &dark, &mut sampler), is(equal_to(Color::black())));
+    }
+
+    #[test]
+    fn normals_should_show_the_normal_of_the_surface() {
+        let scene = Scene::new(vec![Box::new(floor())]);
+
+        let color = Normals.radiance(&ray_down(), &scene, &mut RandomSampler::new(0));
+
+        assert_that!(color, is(equal_to(Color::new(0.5, 1.0, 0.5))));
+    }
+
+    #[test]
+    fn depth_should_fade_with_distance() {
+        let scene = Scene::new(vec![Box::new(floor())]);
+
+        let color = Depth::new(4.0).radiance(&ray_down(), &scene, &mut RandomSampler::new(0));
+
+        assert_that!(color, is(equal_to(Color::new(0.75, 0.75, 0.75))));
+    }
+
+    #[test]
+    fn ambient_occlusion_should_be_white_on_open_ground() {
+        let scene = Scene::new(vec![Box::new(floor())]);
+        let mut sampler = RandomSampler::new(0);
+
+        let color = AmbientOcclusion::new(1.0).radiance(&ray_down(), &scene, &mut sampler);
+
+        assert_that!(color, is(equal_to(Color::white())));
+    }
+}
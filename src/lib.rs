@@ -9,10 +9,12 @@ extern crate hamcrest;
 
 extern crate rand;
 extern crate bmp;
+extern crate png;
 extern crate rayon;
 
 use std::f64;
-use rand::Rng;
+use integrator::{Integrator, PathTracer};
+use render::RenderSettings;
 use prelude::*;
 
 mod scatter;
@@ -21,8 +23,31 @@ mod ray;
 mod color;
 mod camera;
 mod scene;
+mod aabb;
+mod bvh;
+mod material;
+mod triangle;
+mod plane;
+mod cuboid;
+mod cylinder;
+mod torus;
+mod transform;
+mod mesh;
+mod obj;
 mod matrix;
 mod animate;
+pub mod integrator;
+pub mod render;
+mod sampler;
+mod film;
+mod aov;
+mod filter;
+mod checkpoint;
+mod tonemap;
+mod denoise;
+mod solve;
+mod output;
+mod exr;
 
 #[cfg(test)]
 mod tests;
@@ -30,11 +55,35 @@ mod tests;
 pub mod prelude {
     pub use ray::Ray;
     pub use vec::Vec3;
-    pub use matrix::Matrix4;
+    pub use matrix::{Matrix4, Axis};
     pub use color::Color;
     pub use camera::Camera;
-    pub use scene::{Scene, Sphere, Intersectable};
+    pub use scene::{Scene, Sphere, Intersectable, Background};
+    pub use aabb::Aabb;
+    pub use triangle::Triangle;
+    pub use plane::{Plane, Disk, Quad};
+    pub use cuboid::Cuboid;
+    pub use cylinder::{Cylinder, Cone, Capsule};
+    pub use torus::Torus;
+    pub use transform::Transformed;
+    pub use solve::{solve_quadratic, solve_cubic, solve_quartic};
+    pub use material::{Material, Lambertian, Metal, Dielectric, Textured, Texture, Emissive};
+    pub use mesh::{Mesh, MeshError, MeshFace};
+    pub use obj::{load_obj, load_mtl, parse_obj, parse_mtl, MaterialLibrary, ObjError};
     pub use animate::{animate, Keyframes, Keyframe};
+    pub use integrator::{Integrator, PathTracer, Whitted, AmbientOcclusion, Normals, Depth};
+    pub use render::{render, render_pass, render_pass_on, render_with_sample_counts, sample_heatmap, RenderSettings,
+                     RenderThreads, Adaptive};
+    pub use film::{Film, FilmPixel};
+    pub use aov::{Aov, AovPixel};
+    pub use filter::Filter;
+    pub use tonemap::{tone_map, ToneMap};
+    pub use denoise::{denoise, DenoiseSettings};
+    pub use output::{save_image, save_image_as, save_raw_image, save_raw_image_as, srgb_bytes, ImageFormat};
+    pub use exr::{save_exr, write_exr, Layer};
+    pub use checkpoint::{save_checkpoint, load_checkpoint, write_checkpoint, read_checkpoint, scene_hash,
+                         SceneHasher, CheckpointError};
+    pub use sampler::{Sampler, SamplerKind, RandomSampler, StratifiedSampler, HaltonSampler, SobolSampler};
 }
 
 pub fn trace_scene(width: u32,
@@ -43,33 +92,17 @@ pub fn trace_scene(width: u32,
                    camera: &Camera,
                    scene: &Scene)
                    -> Vec<Color> {
-    let mut rng = rand::thread_rng();
-    let mut pixels = Vec::with_capacity((width * height) as usize);
-    for y in 0..height {
-        for x in 0..width {
-            let (x_trans, y_trans) = (x as f64, y as f64);
-            let mut color = Color::black();
-            for _ in 0..num_samples {
-                let u = (x_trans + rng.next_f64()) / width as f64;
-                let v = ((height as f64 - y_trans - 1.0) + rng.next_f64()) / height as f64;
-
-                let ray = camera.create_ray(u, v);
-                color = panic!("Step 2b) Call the 'trace_ray_in_scene' function with the \
-                                appropriate parameters");
-            }
-            color = color / num_samples as f64;
-            pixels.push(color.gamma2());
-        }
-    }
-    pixels
+    trace_scene_with(width, height, num_samples, camera, scene, &PathTracer::new())
 }
 
-fn trace_ray_in_scene(ray: &Ray, scene: &Scene, depth: u32) -> Color {
-    if depth == 50 {
-        return Color::black(); // Return black to avoid being stuck with an unlimited recursion
-    }
-    panic!("Step 2b) Return a gradient by calling the 'gradient' function, passing the ray as \
-            parameter")
+pub fn trace_scene_with(width: u32,
+                        height: u32,
+                        num_samples: u32,
+                        camera: &Camera,
+                        scene: &Scene,
+                        integrator: &dyn Integrator)
+                        -> Vec<Color> {
+    render::render(&RenderSettings::new(width, height, num_samples), camera, scene, integrator)
 }
 
 fn gradient(ray: &Ray) -> Color {
@@ -0,0 +1,326 @@
+use std::sync::Arc;
+use bmp;
+
+use scatter;
+use scene::Intersection;
+use prelude::*;
+
+// Decides how light scatters off a surface, independently of the shape of the surface. A
+// material is shared between shapes through an `Arc<dyn Material>`.
+pub trait Material: Send + Sync {
+    fn scatter(&self,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)>;
+
+    // The radiance the surface gives off by itself, towards the origin of `ray`.
+    fn emitted(&self, _: &Ray, _: &Intersection) -> Color {
+        Color::black()
+    }
+
+    // Whether the surface gives off light, shapes with such a material are sampled as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // The fraction of the light arriving from `direction` that is scattered along `ray` back
+    // towards its origin, including the cosine of the angle to the normal.
+    fn eval(&self, _: &Ray, _: &Intersection, _: Vec3) -> Color {
+        Color::black()
+    }
+
+    // The probability density (per solid angle) of `scatter` picking `direction`. Materials that
+    // scatter in a handful of directions, like mirrors and glass, have no density and return 0,
+    // which makes the integrator rely on `scatter` alone.
+    fn scattering_pdf(&self, _: &Ray, _: &Intersection, _: Vec3) -> f64 {
+        0.0
+    }
+
+    // The color of the surface itself, regardless of the light. Materials that do not tint the
+    // light, like glass, are white.
+    fn albedo(&self, _: &Intersection) -> Color {
+        Color::white()
+    }
+
+    // Feeds everything that decides how the material looks to the hasher, see `scene_hash`.
+    fn hash(&self, hasher: &mut SceneHasher);
+}
+
+pub struct Lambertian {
+    pub albedo: Color,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Lambertian {
+        Lambertian { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)> {
+        scatter::diffusive(self.albedo, ray, intersection, sampler)
+    }
+
+    fn eval(&self, ray: &Ray, intersection: &Intersection, direction: Vec3) -> Color {
+        self.albedo * scatter::diffusive_pdf(ray, intersection, direction)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, intersection: &Intersection, direction: Vec3) -> f64 {
+        scatter::diffusive_pdf(ray, intersection, direction)
+    }
+
+    fn albedo(&self, _: &Intersection) -> Color {
+        self.albedo
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("lambertian");
+        hasher.write_color(self.albedo);
+    }
+}
+
+pub struct Metal {
+    pub albedo: Color,
+    pub diffusiveness: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Color, diffusiveness: f64) -> Metal {
+        Metal { albedo, diffusiveness }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)> {
+        scatter::reflection(self.albedo, self.diffusiveness, ray, intersection, sampler)
+    }
+
+    fn albedo(&self, _: &Intersection) -> Color {
+        self.albedo
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("metal");
+        hasher.write_color(self.albedo);
+        hasher.write_f64(self.diffusiveness);
+    }
+}
+
+pub struct Dielectric {
+    pub refraction_index: f64,
+}
+
+impl Dielectric {
+    pub fn new(refraction_index: f64) -> Dielectric {
+        Dielectric { refraction_index }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)> {
+        scatter::refraction(self.refraction_index, ray, intersection, sampler)
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("dielectric");
+        hasher.write_f64(self.refraction_index);
+    }
+}
+
+// A diffusive material that looks up its color in an image, using the texture coordinates of
+// the intersection when the shape has them.
+pub struct Textured {
+    texture: Texture,
+}
+
+impl Textured {
+    pub fn new(texture: bmp::Image) -> Textured {
+        Textured { texture: Texture::new(&texture) }
+    }
+
+    pub fn open(path: &str) -> bmp::BmpResult<Textured> {
+        bmp::open(path).map(Textured::new)
+    }
+}
+
+// The pixels of an image file as linear colors. Image files store sRGB colors, which are decoded
+// once when the texture is loaded, so the renderer only works with linear ones.
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Texture {
+    pub fn new(image: &bmp::Image) -> Texture {
+        let (width, height) = (image.get_width(), image.get_height());
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = image.get_pixel(x, y);
+                pixels.push(Color::from_srgb8(pixel.r, pixel.g, pixel.b));
+            }
+        }
+        Texture { width, height, pixels }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+impl Material for Textured {
+    fn scatter(&self,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)> {
+        scatter::texture(&self.texture, ray, intersection, sampler)
+    }
+
+    fn eval(&self, ray: &Ray, intersection: &Intersection, direction: Vec3) -> Color {
+        scatter::texture_color(&self.texture, intersection) *
+        scatter::diffusive_pdf(ray, intersection, direction)
+    }
+
+    fn albedo(&self, intersection: &Intersection) -> Color {
+        scatter::texture_color(&self.texture, intersection)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, intersection: &Intersection, direction: Vec3) -> f64 {
+        scatter::diffusive_pdf(ray, intersection, direction)
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("textured");
+        hasher.write_u64(self.texture.width as u64);
+        hasher.write_u64(self.texture.height as u64);
+        for &pixel in self.texture.pixels.iter() {
+            hasher.write_color(pixel);
+        }
+    }
+}
+
+// A light source, it emits the same radiance in every direction and does not scatter light.
+pub struct Emissive {
+    pub radiance: Color,
+}
+
+impl Emissive {
+    pub fn new(radiance: Color) -> Emissive {
+        Emissive { radiance }
+    }
+}
+
+impl Material for Emissive {
+    fn scatter(&self, _: &Ray, _: &Intersection, _: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _: &Ray, _: &Intersection) -> Color {
+        self.radiance
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn albedo(&self, _: &Intersection) -> Color {
+        Color::black()
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("emissive");
+        hasher.write_color(self.radiance);
+    }
+}
+
+pub fn lambertian(albedo: Color) -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(albedo))
+}
+
+pub fn metal(albedo: Color, diffusiveness: f64) -> Arc<dyn Material> {
+    Arc::new(Metal::new(albedo, diffusiveness))
+}
+
+pub fn dielectric(refraction_index: f64) -> Arc<dyn Material> {
+    Arc::new(Dielectric::new(refraction_index))
+}
+
+pub fn emissive(radiance: Color) -> Arc<dyn Material> {
+    Arc::new(Emissive::new(radiance))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use bmp::{Image, Pixel};
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    fn texture() -> Textured {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Pixel::new(255, 0, 0));
+        image.set_pixel(1, 0, Pixel::new(0, 0, 255));
+        Textured::new(image)
+    }
+
+    #[test]
+    fn textured_material_should_use_texture_coordinates_of_intersection() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Color::white());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let i = sphere.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        let (left, _) = texture().scatter(&ray, &i.with_uv((0.1, 0.5)), &mut RandomSampler::new(0)).unwrap();
+
+        assert_that!(left, is(equal_to(Color::red())));
+    }
+
+    #[test]
+    fn textured_material_should_wrap_texture_around_sphere() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Color::white());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let i = sphere.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        let (color, _) = texture().scatter(&ray, &i, &mut RandomSampler::new(0)).unwrap();
+
+        assert_that!(color, is(equal_to(Color::blue())));
+    }
+
+    #[test]
+    fn texture_should_decode_srgb_pixels_into_linear_colors() {
+        let mut image = Image::new(1, 1);
+        image.set_pixel(0, 0, Pixel::new(128, 255, 0));
+
+        let texture = Texture::new(&image);
+
+        assert_that!(texture.pixel(0, 0), is(equal_to(Color::from_srgb8(128, 255, 0))));
+        assert_that!(texture.pixel(0, 0).r, is(less_than(0.25)));
+    }
+
+    #[test]
+    fn material_should_be_shared_between_shapes() {
+        let material: Arc<dyn Material> = Arc::new(Metal::new(Color::white(), 0.0));
+        let sphere = Sphere::with_material(Vec3::new(0.0, 0.0, -1.0), 0.5, material.clone());
+        let triangle = Triangle::with_material(Vec3::new(-1.0, -1.0, -1.0),
+                                               Vec3::new(1.0, -1.0, -1.0),
+                                               Vec3::new(-1.0, 1.0, -1.0),
+                                               material.clone());
+
+        assert_that!(Arc::strong_count(&material), is(equal_to(3)));
+        assert_that!(Arc::ptr_eq(&sphere.material, &triangle.material), is(true));
+    }
+}
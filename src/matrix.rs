@@ -1,10 +1,14 @@
 use std::f64::consts::PI;
+use std::ops::Mul;
+
+use vec::Vec3;
 
 #[derive(Clone, Copy)]
 pub enum Axis {
     X, Y, Z
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Matrix4 {
     pub m: [[f64; 4]; 4]
 }
@@ -12,10 +16,10 @@ pub struct Matrix4 {
 impl Matrix4 {
 
     fn new(m: [[f64; 4]; 4]) -> Matrix4 {
-        Matrix4 { m: m }
+        Matrix4 { m }
     }
 
-    fn create_identity() -> Matrix4 {
+    pub fn identity() -> Matrix4 {
         Matrix4::new([
             [1.0, 0.0, 0.0, 0.0],
             [0.0, 1.0, 0.0, 0.0],
@@ -25,7 +29,7 @@ impl Matrix4 {
     }
 
     pub fn rot(axis: Axis, angle: f64) -> Matrix4 {
-        let mut matrix = Matrix4::create_identity();
+        let mut matrix = Matrix4::identity();
 
         match axis {
           Axis::X => {
@@ -50,7 +54,105 @@ impl Matrix4 {
           }
         }
 
-        return matrix;
+        matrix
+    }
+
+    // Moves points by `offset`, directions stay as they are.
+    pub fn translate(offset: Vec3) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][3] = offset.x;
+        matrix.m[1][3] = offset.y;
+        matrix.m[2][3] = offset.z;
+        matrix
+    }
+
+    // Stretches along the axes by the factors in `factors`, which need not be the same.
+    pub fn scale(factors: Vec3) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][0] = factors.x;
+        matrix.m[1][1] = factors.y;
+        matrix.m[2][2] = factors.z;
+        matrix
+    }
+
+    // Mirrors the matrix along its diagonal, which undoes a rotation. The inverse transpose of a
+    // transform keeps normals at right angles to the surface, even when it scales unevenly.
+    pub fn transpose(&self) -> Matrix4 {
+        let mut matrix = Matrix4::identity();
+        for (i, row) in matrix.m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        matrix
+    }
+
+    // The matrix that undoes this one, by Gauss-Jordan elimination. Matrices that flatten space,
+    // like a scale by 0, can not be undone.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut m = self.m;
+        let mut inverse = Matrix4::identity().m;
+        for column in 0..4 {
+            // The largest value left in the column divides best. NaN sorts above every number, so
+            // it is picked as the pivot and a matrix with NaNs has no inverse.
+            let pivot = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))
+                .unwrap();
+            if m[pivot][column].is_nan() || m[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let divisor = m[column][column];
+            for j in 0..4 {
+                m[column][j] /= divisor;
+                inverse[column][j] /= divisor;
+            }
+            for row in 0..4 {
+                let factor = m[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix4::new(inverse))
+    }
+
+    // How much the matrix changes volumes, negative if it also mirrors them. Only the rotation
+    // and scale part counts, which is all there is to matrices that keep w at 1.
+    pub fn determinant(&self) -> f64 {
+        let m = self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+        m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+        m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Applies the matrix to a direction (or a difference between points), which is not moved by
+    // translations, unlike `matrix * point`.
+    pub fn transform_direction(&self, direction: Vec3) -> Vec3 {
+        let m = self.m;
+        Vec3::new(direction.x * m[0][0] + direction.y * m[0][1] + direction.z * m[0][2],
+                  direction.x * m[1][0] + direction.y * m[1][1] + direction.z * m[1][2],
+                  direction.x * m[2][0] + direction.y * m[2][1] + direction.z * m[2][2])
+    }
+}
+
+// The matrix that applies `matrix` first and then `self`.
+impl Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, matrix: Matrix4) -> Matrix4 {
+        let mut product = Matrix4::new([[0.0; 4]; 4]);
+        for i in 0..4 {
+            for j in 0..4 {
+                product.m[i][j] = (0..4).map(|k| self.m[i][k] * matrix.m[k][j]).sum();
+            }
+        }
+        product
     }
 }
 
@@ -60,10 +162,36 @@ mod tests {
     use std::f64::consts::PI;
     use matrix::Matrix4;
     use matrix::Axis::{X, Y, Z};
+    use vec::Vec3;
+
+    #[test]
+    fn inverse_should_undo_an_affine_transform() {
+        let m = Matrix4::translate(Vec3::new(1.0, 2.0, 3.0)) * Matrix4::rot(Y, 0.5) *
+                Matrix4::scale(Vec3::new(2.0, 0.5, 4.0));
+        let point = Vec3::new(0.3, -0.7, 1.1);
+
+        let inverse = m.inverse().unwrap();
+
+        let back = inverse * (m * point);
+        assert_that!(back.x, is(close_to(point.x, 1e-12)));
+        assert_that!(back.y, is(close_to(point.y, 1e-12)));
+        assert_that!(back.z, is(close_to(point.z, 1e-12)));
+        assert_that!(m.determinant(), is(close_to(4.0, 1e-12)));
+        assert_that!(Matrix4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none(), is(true));
+        assert_that!(Matrix4::scale(Vec3::new(1.0, f64::NAN, 1.0)).inverse().is_none(), is(true));
+    }
+
+    #[test]
+    fn directions_should_not_be_translated() {
+        let m = Matrix4::translate(Vec3::new(1.0, 2.0, 3.0)) * Matrix4::scale(Vec3::new(2.0, 2.0, 2.0));
+
+        assert_that!(m.transform_direction(Vec3::new(1.0, 0.0, 0.0)), is(equal_to(Vec3::new(2.0, 0.0, 0.0))));
+        assert_that!(m * Vec3::new(1.0, 0.0, 0.0), is(equal_to(Vec3::new(3.0, 2.0, 3.0))));
+    }
 
     // #[test]
     fn identity_matrix_can_be_created() {
-        let m = Matrix4::create_identity();
+        let m = Matrix4::identity();
 
         assert_that!(m.m[0][0], is(equal_to(1.0)));
         assert_that!(m.m[1][1], is(equal_to(1.0)));
@@ -0,0 +1,314 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use aabb::Aabb;
+use bvh::Bvh;
+use material::Material;
+use scene::Intersection;
+use triangle::intersect_triangle;
+use prelude::*;
+
+// Indices into the buffers of the owning mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+// A face that refers past the end of one of the buffers of the mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshError {
+    pub face: usize,
+    pub buffer: &'static str,
+    pub index: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "face {} uses {} {}, but the mesh has {} {}(s)",
+               self.face,
+               self.buffer,
+               self.index,
+               self.len,
+               self.buffer)
+    }
+}
+
+impl Error for MeshError {}
+
+struct MeshData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<MeshFace>,
+    materials: Vec<Arc<dyn Material>>,
+    center: Vec3,
+    bvh: Bvh,
+}
+
+impl MeshData {
+    // The buffers as they are, the center and the BVH follow from them.
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_u64(self.positions.len() as u64);
+        for &position in self.positions.iter() {
+            hasher.write_vec3(position);
+        }
+        hasher.write_u64(self.normals.len() as u64);
+        for &normal in self.normals.iter() {
+            hasher.write_vec3(normal);
+        }
+        hasher.write_u64(self.uvs.len() as u64);
+        for &(u, v) in self.uvs.iter() {
+            hasher.write_f64(u);
+            hasher.write_f64(v);
+        }
+        hasher.write_u64(self.faces.len() as u64);
+        for face in self.faces.iter() {
+            for indices in [Some(face.positions), face.normals, face.uvs].iter() {
+                match *indices {
+                    Some(indices) => indices.iter().for_each(|&index| hasher.write_u64(index as u64)),
+                    None => hasher.write_u64(u64::MAX),
+                }
+            }
+            hasher.write_u64(face.material as u64);
+        }
+        hasher.write_u64(self.materials.len() as u64);
+        for material in self.materials.iter() {
+            material.hash(hasher);
+        }
+    }
+}
+
+// The buffers are shared between every copy of the mesh, so moving it (or handing out the hit
+// face in an Intersection) never copies the geometry.
+#[derive(Clone)]
+pub struct Mesh {
+    data: Arc<MeshData>,
+    offset: Vec3,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vec3>,
+               normals: Vec<Vec3>,
+               uvs: Vec<(f64, f64)>,
+               faces: Vec<MeshFace>,
+               materials: Vec<Arc<dyn Material>>)
+               -> Result<Mesh, MeshError> {
+        check_faces(&faces, positions.len(), normals.len(), uvs.len(), materials.len())?;
+        let center = if positions.is_empty() {
+            Vec3::new(0.0, 0.0, 0.0)
+        } else {
+            positions.iter().fold(Vec3::new(0.0, 0.0, 0.0), |sum, &p| sum + p) /
+            positions.len() as f64
+        };
+        let bounds: Vec<Option<Aabb>> = faces.iter()
+            .map(|face| {
+                let [a, b, c] = face.positions;
+                Some(Aabb::from_points(&[positions[a], positions[b], positions[c]]))
+            })
+            .collect();
+        let bvh = Bvh::new(&bounds);
+        Ok(Mesh {
+            data: Arc::new(MeshData {
+                positions,
+                normals,
+                uvs,
+                faces,
+                materials,
+                center,
+                bvh,
+            }),
+            offset: Vec3::new(0.0, 0.0, 0.0),
+        })
+    }
+
+    pub fn num_faces(&self) -> usize {
+        self.data.faces.len()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.data.center + self.offset
+    }
+
+    fn intersects_face(&self,
+                       index: usize,
+                       ray: &Ray,
+                       t_min: f64,
+                       t_max: f64)
+                       -> Option<Intersection> {
+        let data = &self.data;
+        let face = &data.faces[index];
+        let local_ray = Ray::new(ray.origin - self.offset, ray.direction);
+        let [a, b, c] = face.positions;
+        let (delta, u, v) = intersect_triangle(&local_ray,
+                                               data.positions[a],
+                                               data.positions[b],
+                                               data.positions[c],
+                                               t_min,
+                                               t_max)?;
+        let w = 1.0 - u - v;
+
+        let normal = match face.normals {
+            Some([na, nb, nc]) => {
+                (w * data.normals[na] + u * data.normals[nb] + v * data.normals[nc]).normalize()
+            }
+            None => {
+                (data.positions[b] - data.positions[a])
+                    .cross(data.positions[c] - data.positions[a])
+                    .normalize()
+            }
+        };
+        let intersection = Intersection::new(delta,
+                                             ray.point_along_direction(delta),
+                                             normal,
+                                             Box::new(MeshTriangle {
+                                                 mesh: self.clone(),
+                                                 index,
+                                             }))
+            .with_barycentric((w, u, v));
+
+        Some(match face.uvs {
+            Some([ta, tb, tc]) => {
+                let (ua, va) = data.uvs[ta];
+                let (ub, vb) = data.uvs[tb];
+                let (uc, vc) = data.uvs[tc];
+                intersection.with_uv((w * ua + u * ub + v * uc, w * va + u * vb + v * vc))
+            }
+            None => intersection,
+        })
+    }
+}
+
+// Every index of every face has to be inside its buffer, or intersecting the face would panic.
+fn check_faces(faces: &[MeshFace],
+               num_positions: usize,
+               num_normals: usize,
+               num_uvs: usize,
+               num_materials: usize)
+               -> Result<(), MeshError> {
+    for (face, indices) in faces.iter().enumerate() {
+        let buffers = [("position", Some(indices.positions), num_positions),
+                       ("normal", indices.normals, num_normals),
+                       ("texture coordinate", indices.uvs, num_uvs),
+                       ("material", Some([indices.material; 3]), num_materials)];
+        for &(buffer, indices, len) in buffers.iter() {
+            let out_of_range = indices.iter().flat_map(|indices| indices.iter()).find(|&&index| index >= len);
+            if let Some(&index) = out_of_range {
+                return Err(MeshError {
+                    face,
+                    buffer,
+                    index,
+                    len,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Intersectable for Mesh {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let local_ray = Ray::new(ray.origin - self.offset, ray.direction);
+        self.data.bvh.intersects(&local_ray, t_min, t_max, |index, closest_so_far| {
+            self.intersects_face(index, ray, t_min, closest_so_far)
+        })
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.data.materials.iter().map(|material| &**material).collect()
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        Box::new(Mesh {
+            data: self.data.clone(),
+            offset: vec - self.data.center,
+        })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.data.bvh.aabb().map(|aabb| aabb.translate(self.offset))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("mesh");
+        self.data.hash(hasher);
+        hasher.write_vec3(self.offset);
+    }
+}
+
+// The face of a mesh that was hit, it knows which material to scatter with. Faces that refer
+// to a material the mesh does not have absorb every ray.
+#[derive(Clone)]
+struct MeshTriangle {
+    mesh: Mesh,
+    index: usize,
+}
+
+impl Intersectable for MeshTriangle {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        self.mesh.intersects_face(self.index, ray, t_min, t_max)
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        let data = &self.mesh.data;
+        data.materials.get(data.faces[self.index].material).map(|material| &**material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        self.mesh.move_to(vec)
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let [a, b, c] = self.mesh.data.faces[self.index].positions;
+        let positions = &self.mesh.data.positions;
+        Some(Aabb::from_points(&[positions[a], positions[b], positions[c]]).translate(self.mesh.offset))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("mesh face");
+        self.mesh.hash(hasher);
+        hasher.write_u64(self.index as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use material;
+    use mesh::{Mesh, MeshError, MeshFace};
+    use prelude::*;
+
+    fn triangle(face: MeshFace) -> Result<Mesh, MeshError> {
+        let positions = vec![Vec3::new(-1.0, 0.0, -2.0),
+                             Vec3::new(1.0, 0.0, -2.0),
+                             Vec3::new(0.0, 1.0, -2.0)];
+        let normals = vec![Vec3::new(0.0, 0.0, 1.0)];
+        Mesh::new(positions, normals, vec![], vec![face], vec![material::lambertian(Color::white())])
+    }
+
+    #[test]
+    fn a_mesh_should_accept_faces_inside_its_buffers() {
+        let face = MeshFace { positions: [0, 1, 2], normals: Some([0, 0, 0]), uvs: None, material: 0 };
+
+        assert_that!(triangle(face).is_ok(), is(true));
+    }
+
+    #[test]
+    fn a_mesh_should_reject_faces_outside_its_buffers() {
+        let face = MeshFace { positions: [0, 1, 2], normals: None, uvs: None, material: 0 };
+        let error = |face: MeshFace| triangle(face).err().map(|error| (error.buffer, error.index, error.len));
+
+        assert_that!(error(MeshFace { positions: [0, 3, 2], ..face }),
+                     is(equal_to(Some(("position", 3, 3)))));
+        assert_that!(error(MeshFace { normals: Some([0, 1, 0]), ..face }),
+                     is(equal_to(Some(("normal", 1, 1)))));
+        assert_that!(error(MeshFace { uvs: Some([0, 0, 0]), ..face }),
+                     is(equal_to(Some(("texture coordinate", 0, 0)))));
+        assert_that!(error(MeshFace { material: 1, ..face }), is(equal_to(Some(("material", 1, 1)))));
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+use material::{self, Material};
+use mesh::{Mesh, MeshError, MeshFace};
+use prelude::*;
+
+// The color of faces that do not use a material, and of materials without a `Kd` statement.
+const DEFAULT_COLOR: Color = Color {
+    r: 0.8,
+    g: 0.8,
+    b: 0.8,
+};
+
+pub type MaterialLibrary = HashMap<String, Arc<dyn Material>>;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    // The faces of the file refer past the end of its buffers.
+    Mesh(String, MeshError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io(ref path, ref error) => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse { ref file, line, ref message } => {
+                write!(f, "{}:{}: {}", file, line, message)
+            }
+            ObjError::Mesh(ref file, ref error) => write!(f, "{}: {}", file, error),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Mesh, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_obj(&source,
+              &path.display().to_string(),
+              |name| load_mtl(directory.join(name)))
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<MaterialLibrary, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    parse_mtl(&source, &path.display().to_string())
+}
+
+// `load_mtl` is called with the file name of every `mtllib` statement, this lets the caller
+// decide where material libraries are read from.
+pub fn parse_obj<F>(source: &str, file: &str, mut load_mtl: F) -> Result<Mesh, ObjError>
+    where F: FnMut(&str) -> Result<MaterialLibrary, ObjError>
+{
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut faces = Vec::new();
+    let mut materials = vec![material::lambertian(DEFAULT_COLOR)];
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut library = MaterialLibrary::new();
+    let mut current_material = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| {
+            ObjError::Parse {
+                file: file.to_string(),
+                line: index + 1,
+                message,
+            }
+        };
+        let mut tokens = strip_comment(line).split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => positions.push(parse_vec3(&mut tokens).map_err(&error)?),
+            "vn" => normals.push(parse_vec3(&mut tokens).map_err(&error)?),
+            "vt" => {
+                let u = parse_f64(tokens.next(), "u").map_err(&error)?;
+                let v = match tokens.next() {
+                    Some(token) => parse_f64(Some(token), "v").map_err(&error)?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut vertices = Vec::new();
+                for token in tokens {
+                    vertices.push(parse_face_vertex(token,
+                                                    positions.len(),
+                                                    uvs.len(),
+                                                    normals.len())
+                        .map_err(&error)?);
+                }
+                if vertices.len() < 3 {
+                    return Err(error(format!("a face needs at least 3 vertices, found {}",
+                                             vertices.len())));
+                }
+                // Polygons are triangulated as a fan around their first vertex.
+                for i in 1..vertices.len() - 1 {
+                    let (a, b, c) = (vertices[0], vertices[i], vertices[i + 1]);
+                    faces.push(MeshFace {
+                        positions: [a.0, b.0, c.0],
+                        uvs: match (a.1, b.1, c.1) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None,
+                        },
+                        normals: match (a.2, b.2, c.2) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None,
+                        },
+                        material: current_material,
+                    });
+                }
+            }
+            "mtllib" => {
+                for name in tokens {
+                    library.extend(load_mtl(name)?);
+                }
+            }
+            "usemtl" => {
+                let name = tokens.next()
+                    .ok_or_else(|| error("missing material name".to_string()))?;
+                current_material = match material_indices.get(name) {
+                    Some(&index) => index,
+                    None => {
+                        let material = library.get(name)
+                            .cloned()
+                            .ok_or_else(|| error(format!("unknown material `{}`", name)))?;
+                        materials.push(material);
+                        material_indices.insert(name.to_string(), materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+            }
+            // Groups, objects, smoothing groups, curves and other statements do not affect
+            // how the triangles are rendered.
+            _ => (),
+        }
+    }
+
+    Mesh::new(positions, normals, uvs, faces, materials)
+        .map_err(|error| ObjError::Mesh(file.to_string(), error))
+}
+
+// The statements of a material are mapped onto the behaviours of the ray tracer:
+// transparent materials (`d` < 1, `Tr` > 0 or a transparent `illum` model) are dielectrics with
+// the index of refraction `Ni`, mirror materials (`illum` 3, 5 or 8, or only a specular color)
+// are metals reflecting `Ks` where the shininess `Ns` controls how diffuse the reflection is, and
+// everything else is lambertian with the color `Kd`.
+pub fn parse_mtl(source: &str, file: &str) -> Result<MaterialLibrary, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlStatements)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| {
+            ObjError::Parse {
+                file: file.to_string(),
+                line: index + 1,
+                message,
+            }
+        };
+        let mut tokens = strip_comment(line).split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = tokens.next().ok_or_else(|| error("missing material name".to_string()))?;
+            if let Some((name, statements)) = current.take() {
+                materials.insert(name, statements.to_material());
+            }
+            current = Some((name.to_string(), MtlStatements::default()));
+            continue;
+        }
+
+        let statements = match current {
+            Some((_, ref mut statements)) => statements,
+            None => return Err(error(format!("`{}` before `newmtl`", keyword))),
+        };
+        match keyword {
+            "Kd" => statements.diffuse = parse_color(&mut tokens).map_err(&error)?,
+            "Ks" => statements.specular = parse_color(&mut tokens).map_err(&error)?,
+            "Ns" => statements.shininess = Some(parse_f64(tokens.next(), "Ns").map_err(&error)?),
+            "Ni" => statements.refraction_index = Some(parse_f64(tokens.next(), "Ni").map_err(&error)?),
+            "d" => statements.dissolve = parse_f64(tokens.next(), "d").map_err(&error)?,
+            "Tr" => statements.dissolve = 1.0 - parse_f64(tokens.next(), "Tr").map_err(&error)?,
+            "illum" => {
+                let token = tokens.next()
+                    .ok_or_else(|| error("missing illumination model".to_string()))?;
+                statements.illum = Some(token.parse()
+                    .map_err(|_| error(format!("invalid illumination model `{}`", token)))?);
+            }
+            // Texture maps, ambient color and the rest are not supported.
+            _ => (),
+        }
+    }
+
+    if let Some((name, statements)) = current {
+        materials.insert(name, statements.to_material());
+    }
+    Ok(materials)
+}
+
+struct MtlStatements {
+    diffuse: Color,
+    specular: Color,
+    shininess: Option<f64>,
+    refraction_index: Option<f64>,
+    dissolve: f64,
+    illum: Option<u32>,
+}
+
+impl Default for MtlStatements {
+    fn default() -> MtlStatements {
+        MtlStatements {
+            diffuse: DEFAULT_COLOR,
+            specular: Color::black(),
+            shininess: None,
+            refraction_index: None,
+            dissolve: 1.0,
+            illum: None,
+        }
+    }
+}
+
+impl MtlStatements {
+    fn to_material(&self) -> Arc<dyn Material> {
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9));
+        let mirror = match self.illum {
+            Some(3) | Some(5) | Some(8) => true,
+            Some(_) => false,
+            None => self.diffuse == Color::black() && self.specular != Color::black(),
+        };
+        if transparent {
+            material::dielectric(self.refraction_index.unwrap_or(1.5))
+        } else if mirror {
+            // Maps the Phong exponent onto a roughness, a sharp highlight gives a sharp mirror.
+            let diffusiveness = self.shininess.map_or(0.0, |ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt());
+            let color = if self.specular == Color::black() {
+                self.diffuse
+            } else {
+                self.specular
+            };
+            material::metal(color, diffusiveness)
+        } else {
+            material::lambertian(self.diffuse)
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|error| ObjError::Io(path.to_path_buf(), error))?;
+    Ok(source)
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+fn parse_f64(token: Option<&str>, name: &str) -> Result<f64, String> {
+    match token {
+        Some(token) => token.parse().map_err(|_| format!("invalid number `{}` for {}", token, name)),
+        None => Err(format!("missing {}", name)),
+    }
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace) -> Result<Vec3, String> {
+    Ok(Vec3::new(parse_f64(tokens.next(), "x")?,
+                 parse_f64(tokens.next(), "y")?,
+                 parse_f64(tokens.next(), "z")?))
+}
+
+fn parse_color(tokens: &mut SplitWhitespace) -> Result<Color, String> {
+    let r = parse_f64(tokens.next(), "r")?;
+    // A single value is a grey color.
+    match tokens.next() {
+        Some(g) => Ok(Color::new(r, parse_f64(Some(g), "g")?, parse_f64(tokens.next(), "b")?)),
+        None => Ok(Color::new(r, r, r)),
+    }
+}
+
+// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn` into zero based indices.
+fn parse_face_vertex(token: &str,
+                     num_positions: usize,
+                     num_uvs: usize,
+                     num_normals: usize)
+                     -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = token.split('/');
+    let position = match parts.next() {
+        Some(part) if !part.is_empty() => resolve_index(part, num_positions, "vertex")?,
+        _ => return Err(format!("missing vertex index in `{}`", token)),
+    };
+    let uv = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, num_uvs, "texture coordinate")?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, num_normals, "normal")?),
+        _ => None,
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex `{}`", token));
+    }
+    Ok((position, uv, normal))
+}
+
+// OBJ indices start at 1, negative indices count backwards from the last element.
+fn resolve_index(token: &str, len: usize, name: &str) -> Result<usize, String> {
+    let index: i64 = token.parse().map_err(|_| format!("invalid {} index `{}`", name, token))?;
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        Err(format!("{} index {} is out of range, {} {}(s) defined so far", name, index, len, name))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use obj::{parse_obj, parse_mtl, MaterialLibrary, ObjError};
+    use prelude::*;
+
+    fn no_materials(name: &str) -> Result<MaterialLibrary, ObjError> {
+        panic!("unexpected material library {}", name)
+    }
+
+    fn error_line(error: ObjError) -> usize {
+        match error {
+            ObjError::Parse { line, .. } => line,
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn should_triangulate_polygons() {
+        let source = "v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\nf 1 2 3 4\n";
+
+        let mesh = parse_obj(source, "quad.obj", no_materials).unwrap();
+
+        assert_that!(mesh.num_faces(), is(equal_to(2)));
+    }
+
+    #[test]
+    fn should_intersect_loaded_mesh_with_normals_and_uvs() {
+        let source = "# a triangle\n\
+                      v -1 -1 -1\nv 1 -1 -1\nv -1 1 -1\n\
+                      vt 0 0\nvt 1 0\nvt 0 1\n\
+                      vn 0 0 1\n\
+                      f -3/1/1 -2/2/1 -1/3/1\n";
+        let mesh = parse_obj(source, "triangle.obj", no_materials).unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = mesh.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(1.0)));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 0.0, 1.0))));
+        assert_that!(i.uv, is(equal_to(Some((0.5, 0.5)))));
+    }
+
+    #[test]
+    fn should_report_line_of_invalid_number() {
+        let source = "v 0 0 0\n\nv 1 x 0\n";
+
+        let error = parse_obj(source, "broken.obj", no_materials).err().unwrap();
+
+        assert_that!(error_line(error), is(equal_to(3)));
+    }
+
+    #[test]
+    fn should_report_index_out_of_range() {
+        let source = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+
+        let error = parse_obj(source, "broken.obj", no_materials).err().unwrap();
+
+        assert_that!(error.to_string(),
+                     is(equal_to("broken.obj:3: vertex index 3 is out of range, 2 vertex(s) \
+                                  defined so far"
+                         .to_string())));
+    }
+
+    #[test]
+    fn should_report_unknown_material() {
+        let source = "mtllib scene.mtl\nusemtl missing\n";
+
+        let error = parse_obj(source, "scene.obj", |_| Ok(MaterialLibrary::new())).err().unwrap();
+
+        assert_that!(error_line(error), is(equal_to(2)));
+    }
+
+    #[test]
+    fn should_map_mtl_materials_onto_scatter_behaviours() {
+        let source = "newmtl matte\nKd 0.5 0.2 0.1\nKs 0.5 0.5 0.5\nillum 2\n\
+                      newmtl mirror\nKd 0 0 0\nKs 0.9 0.9 0.9\nNs 1000000\n\
+                      newmtl glass\nKd 0.5 0.5 0.5\nNi 1.33\nd 0.1\n";
+        let materials = parse_mtl(source, "scene.mtl").unwrap();
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0).normalize());
+        let triangle = Triangle::new(Vec3::new(-1.0, 0.0, 1.0),
+                                     Vec3::new(1.0, 0.0, 1.0),
+                                     Vec3::new(0.0, 0.0, -1.0),
+                                     Color::white());
+        let i = triangle.intersects(&ray, 0.0, 1000.0).unwrap();
+        let mut sampler = RandomSampler::new(0);
+
+        let (matte, _) = materials["matte"].scatter(&ray, &i, &mut sampler).unwrap();
+        let (mirror, reflected) = materials["mirror"].scatter(&ray, &i, &mut sampler).unwrap();
+        let (glass, _) = materials["glass"].scatter(&ray, &i, &mut sampler).unwrap();
+
+        assert_that!(matte, is(equal_to(Color::new(0.5, 0.2, 0.1))));
+        assert_that!(mirror, is(equal_to(Color::new(0.9, 0.9, 0.9))));
+        assert_that!(reflected.direction.dot(Vec3::new(1.0, 1.0, 0.0).normalize()),
+                     is(greater_than(0.99)));
+        assert_that!(glass, is(equal_to(Color::white())));
+    }
+
+    #[test]
+    fn should_report_mtl_statement_before_newmtl() {
+        let error = parse_mtl("# materials\nKd 1 1 1\n", "scene.mtl").err().unwrap();
+
+        assert_that!(error_line(error), is(equal_to(2)));
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use bmp;
+use png;
+
+use exr::{self, Layer};
+use prelude::*;
+
+// The image formats the renderer can save to. The pixels given to `save_image` are the radiance
+// that reached the camera, in linear RGB. PNG, PPM and BMP store sRGB encoded colors clamped to
+// 0..1, Radiance HDR and OpenEXR store the radiance itself, so bright pixels keep their
+// value. `save_exr` can also store the output variables of a film next to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Bmp,
+    Png,
+    // PNG with 16 bits per channel, for smoother gradients.
+    Png16,
+    Ppm,
+    Hdr,
+    Exr,
+}
+
+impl ImageFormat {
+    // The format of a file name's extension, `.png` gives 8 bit PNG.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "bmp" => Some(ImageFormat::Bmp),
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+// Saves the image in the format of the file name's extension.
+pub fn save_image<P: AsRef<Path>>(path: P, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    let path = path.as_ref();
+    save_image_as(path, format_of(path)?, width, height, pixels)
+}
+
+pub fn save_image_as<P: AsRef<Path>>(path: P,
+                                     format: ImageFormat,
+                                     width: u32,
+                                     height: u32,
+                                     pixels: &[Color])
+                                     -> io::Result<()> {
+    save(path.as_ref(), format, width, height, pixels, Encoding::Srgb)
+}
+
+// Saves pixels that already are the values to show, like the normals of `film.aov_image()` or a
+// `sample_heatmap()`, so they are stored as they are instead of sRGB encoded: 0.5 is stored as 128
+// in an 8 bit image.
+pub fn save_raw_image<P: AsRef<Path>>(path: P, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    let path = path.as_ref();
+    save_raw_image_as(path, format_of(path)?, width, height, pixels)
+}
+
+pub fn save_raw_image_as<P: AsRef<Path>>(path: P,
+                                         format: ImageFormat,
+                                         width: u32,
+                                         height: u32,
+                                         pixels: &[Color])
+                                         -> io::Result<()> {
+    save(path.as_ref(), format, width, height, pixels, Encoding::Raw)
+}
+
+// The 8 bit channels of the pixels, three per pixel, sRGB encoded and clamped like in a BMP, PNG or
+// PPM image. For images that are written with other libraries, like the frames of a GIF.
+pub fn srgb_bytes(pixels: &[Color]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(3 * pixels.len());
+    for &color in pixels {
+        bytes.extend(display(color, Encoding::Srgb).iter().map(|&value| to_u8(value)));
+    }
+    bytes
+}
+
+// How colors are turned into the values of an image with 8 or 16 bits per channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Srgb,
+    Raw,
+}
+
+fn format_of(path: &Path) -> io::Result<ImageFormat> {
+    ImageFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput,
+                       format!("{}: unknown image format, use .bmp, .png, .ppm, .hdr or .exr",
+                               path.display()))
+    })
+}
+
+fn save(path: &Path,
+        format: ImageFormat,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+        encoding: Encoding)
+        -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height) as usize, "the image should have width * height pixels");
+    match format {
+        ImageFormat::Bmp => return write_bmp(path, width, height, pixels, encoding),
+        ImageFormat::Exr => {
+            let values = pixels.iter().flat_map(|c| vec![c.r as f32, c.g as f32, c.b as f32]).collect();
+            return exr::save_exr(path, width, height, &[Layer::new("", vec!["R", "G", "B"], values)]);
+        }
+        _ => (),
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png => write_png(&mut writer, width, height, pixels, png::BitDepth::Eight, encoding)?,
+        ImageFormat::Png16 => write_png(&mut writer, width, height, pixels, png::BitDepth::Sixteen, encoding)?,
+        ImageFormat::Ppm => write_ppm(&mut writer, width, height, pixels, encoding)?,
+        ImageFormat::Hdr => write_hdr(&mut writer, width, height, pixels)?,
+        ImageFormat::Bmp | ImageFormat::Exr => unreachable!(),
+    }
+    writer.flush()
+}
+
+// The displayed value of every channel, between 0 and 1.
+fn display(color: Color, encoding: Encoding) -> [f64; 3] {
+    let color = if encoding == Encoding::Srgb { color.to_srgb() } else { color };
+    [clamp(color.r), clamp(color.g), clamp(color.b)]
+}
+
+// NaN, which a broken shape or material can produce, is shown as black rather than white.
+fn clamp(value: f64) -> f64 {
+    if value > 0.0 { value.min(1.0) } else { 0.0 }
+}
+
+fn to_u8(value: f64) -> u8 {
+    (value * 255.0).round() as u8
+}
+
+fn to_u16(value: f64) -> u16 {
+    (value * 65535.0).round() as u16
+}
+
+fn write_bmp(path: &Path, width: u32, height: u32, pixels: &[Color], encoding: Encoding) -> io::Result<()> {
+    let mut image = bmp::Image::new(width, height);
+    for (x, y) in image.coordinates() {
+        let [r, g, b] = display(pixels[(y * width + x) as usize], encoding);
+        image.set_pixel(x, y, bmp::Pixel::new(to_u8(r), to_u8(g), to_u8(b)));
+    }
+    image.save(&path.to_string_lossy())
+}
+
+fn write_png<W: Write>(writer: W,
+                       width: u32,
+                       height: u32,
+                       pixels: &[Color],
+                       bit_depth: png::BitDepth,
+                       encoding: Encoding)
+                       -> io::Result<()> {
+    let mut data = Vec::new();
+    for &color in pixels {
+        for &value in display(color, encoding).iter() {
+            match bit_depth {
+                png::BitDepth::Sixteen => data.extend_from_slice(&to_u16(value).to_be_bytes()),
+                _ => data.push(to_u8(value)),
+            }
+        }
+    }
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(bit_depth);
+    let mut writer = encoder.write_header().map_err(to_io_error)?;
+    writer.write_image_data(&data).map_err(to_io_error)?;
+    writer.finish().map_err(to_io_error)
+}
+
+fn to_io_error(error: png::EncodingError) -> io::Error {
+    match error {
+        png::EncodingError::IoError(error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+// The binary variant (P6) of the portable pixmap.
+fn write_ppm<W: Write>(writer: &mut W,
+                       width: u32,
+                       height: u32,
+                       pixels: &[Color],
+                       encoding: Encoding)
+                       -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    for &color in pixels {
+        let [r, g, b] = display(color, encoding);
+        writer.write_all(&[to_u8(r), to_u8(g), to_u8(b)])?;
+    }
+    Ok(())
+}
+
+// Radiance RGBE, without run length encoding: every pixel is three 8 bit mantissas that share an
+// exponent, see "Real Pixels" (Ward 1991).
+fn write_hdr<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    write!(writer,
+           "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+           height,
+           width)?;
+    for &color in pixels {
+        writer.write_all(&rgbe(color))?;
+    }
+    Ok(())
+}
+
+fn rgbe(color: Color) -> [u8; 4] {
+    let (r, g, b) = (color.r.max(0.0), color.g.max(0.0), color.b.max(0.0));
+    let max = r.max(g).max(b);
+    if max < 1e-32 || !max.is_finite() {
+        return [0, 0, 0, 0];
+    }
+    // max = mantissa * 2^exponent with the mantissa in [0.5, 1).
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    [(r * scale).min(255.0) as u8,
+     (g * scale).min(255.0) as u8,
+     (b * scale).min(255.0) as u8,
+     (exponent + 128) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use png;
+    use prelude::*;
+    use super::{ImageFormat, Encoding, srgb_bytes, write_png, write_ppm, write_hdr, rgbe};
+
+    #[test]
+    fn format_should_follow_the_extension() {
+        assert_that!(ImageFormat::from_path("scene.png"), is(equal_to(Some(ImageFormat::Png))));
+        assert_that!(ImageFormat::from_path("out/scene.HDR"), is(equal_to(Some(ImageFormat::Hdr))));
+        assert_that!(ImageFormat::from_path("scene.ppm"), is(equal_to(Some(ImageFormat::Ppm))));
+        assert_that!(ImageFormat::from_path("scene.bmp"), is(equal_to(Some(ImageFormat::Bmp))));
+        assert_that!(ImageFormat::from_path("scene.exr"), is(equal_to(Some(ImageFormat::Exr))));
+        assert_that!(ImageFormat::from_path("scene.jpg"), is(equal_to(None)));
+        assert_that!(ImageFormat::from_path("scene"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn ppm_should_clamp_bright_pixels_instead_of_overflowing() {
+        let mut bytes = Vec::new();
+
+        write_ppm(&mut bytes,
+                  3,
+                  1,
+                  &[Color::new(4.0, 0.25, -1.0), Color::white(), Color::new(f64::NAN, 0.0, 0.0)],
+                  Encoding::Srgb)
+            .unwrap();
+
+        assert_that!(bytes,
+                     is(equal_to(b"P6\n3 1\n255\n\xff\x89\x00\xff\xff\xff\x00\x00\x00".to_vec())));
+    }
+
+    #[test]
+    fn bytes_should_match_the_pixels_of_a_saved_image() {
+        let pixels = [Color::new(4.0, 0.25, -1.0), Color::white(), Color::new(f64::NAN, 0.0, 0.0)];
+
+        assert_that!(srgb_bytes(&pixels), is(equal_to(b"\xff\x89\x00\xff\xff\xff\x00\x00\x00".to_vec())));
+    }
+
+    #[test]
+    fn raw_pixels_should_be_stored_as_they_are() {
+        let mut bytes = Vec::new();
+
+        write_ppm(&mut bytes, 1, 1, &[Color::new(0.5, 0.25, 2.0)], Encoding::Raw).unwrap();
+
+        assert_that!(bytes, is(equal_to(b"P6\n1 1\n255\n\x80\x40\xff".to_vec())));
+    }
+
+    #[test]
+    fn png_should_decode_to_the_same_pixels() {
+        let pixels = [Color::new(0.25, 1.0, 9.0), Color::black()];
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, 2, 1, &pixels, png::BitDepth::Sixteen, Encoding::Srgb).unwrap();
+
+        let mut reader = png::Decoder::new(&bytes[..]).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+
+        assert_that!(info.bit_depth, is(equal_to(png::BitDepth::Sixteen)));
+        assert_that!(data[..info.buffer_size()].to_vec(),
+                     is(equal_to(vec![0x89, 0x7f, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn hdr_should_keep_bright_pixels() {
+        let mut bytes = Vec::new();
+
+        write_hdr(&mut bytes, 1, 1, &[Color::new(1.0, 0.5, 12.0)]).unwrap();
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 1\n";
+        assert_that!(bytes[..header.len()].to_vec(), is(equal_to(header.to_vec())));
+        assert_that!(bytes[header.len()..].to_vec(), is(equal_to(vec![16, 8, 192, 132])));
+        assert_that!(rgbe(Color::white()), is(equal_to([128, 128, 128, 129])));
+        assert_that!(rgbe(Color::black()), is(equal_to([0, 0, 0, 0])));
+    }
+}
@@ -0,0 +1,384 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use aabb::Aabb;
+use material::{self, Material};
+use scene::Intersection;
+use prelude::*;
+
+const EPSILON: f64 = 0.00000001;
+
+// An infinite plane through `point`, for floors and walls. Textures repeat every unit along the
+// plane.
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, color: Color) -> Plane {
+        Plane::with_material(point, normal, material::lambertian(color))
+    }
+
+    pub fn reflective(point: Vec3, normal: Vec3, color: Color, diffusiveness: f64) -> Plane {
+        Plane::with_material(point, normal, material::metal(color, diffusiveness))
+    }
+
+    pub fn with_material(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Plane {
+        Plane {
+            point,
+            normal: normal.normalize(),
+            material,
+        }
+    }
+}
+
+impl Intersectable for Plane {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let delta = intersect_plane(ray, self.point, self.normal, t_min, t_max)?;
+        let point = ray.point_along_direction(delta);
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let offset = point - self.point;
+        Some(Intersection::new(delta, point, self.normal, Box::new(self.clone()))
+            .with_uv((offset.dot(tangent), offset.dot(bitangent))))
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        Box::new(Plane { point: vec, ..self.clone() })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("plane");
+        hasher.write_vec3(self.point);
+        hasher.write_vec3(self.normal);
+        self.material.hash(hasher);
+    }
+}
+
+// A flat disk around `center`, like the top of a table or a round lamp.
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, color: Color) -> Disk {
+        Disk::with_material(center, normal, radius, material::lambertian(color))
+    }
+
+    pub fn emissive(center: Vec3, normal: Vec3, radius: f64, radiance: Color) -> Disk {
+        Disk::with_material(center, normal, radius, material::emissive(radiance))
+    }
+
+    pub fn with_material(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Disk {
+        Disk {
+            center,
+            normal: normal.normalize(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Intersectable for Disk {
+    // The texture coordinates go around the disk (u) and from its center to its rim (v).
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let delta = intersect_plane(ray, self.center, self.normal, t_min, t_max)?;
+        let point = ray.point_along_direction(delta);
+        let offset = point - self.center;
+        let distance = offset.length();
+        if distance > self.radius {
+            return None;
+        }
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let angle = offset.dot(bitangent).atan2(offset.dot(tangent));
+        let u = if angle < 0.0 { angle / (2.0 * PI) + 1.0 } else { angle / (2.0 * PI) };
+        Some(Intersection::new(delta, point, self.normal, Box::new(self.clone()))
+            .with_uv((u, distance / self.radius)))
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn is_samplable(&self) -> bool {
+        true
+    }
+
+    // Picks a point uniformly on the area of the disk.
+    fn sample_towards(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let (s, t) = sampler.next_2d();
+        let (r, phi) = (self.radius * s.sqrt(), 2.0 * PI * t);
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        Some(self.center + r * phi.cos() * tangent + r * phi.sin() * bitangent - origin)
+    }
+
+    fn pdf_towards(&self, origin: Vec3, direction: Vec3) -> f64 {
+        pdf_per_area(self, origin, direction, PI * self.radius * self.radius)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        Box::new(Disk { center: vec, ..self.clone() })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        // How far the rim reaches along every axis.
+        let n = self.normal;
+        let extent = self.radius *
+                     Vec3::new((1.0 - n.x * n.x).max(0.0).sqrt(),
+                               (1.0 - n.y * n.y).max(0.0).sqrt(),
+                               (1.0 - n.z * n.z).max(0.0).sqrt());
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("disk");
+        hasher.write_vec3(self.center);
+        hasher.write_vec3(self.normal);
+        hasher.write_f64(self.radius);
+        self.material.hash(hasher);
+    }
+}
+
+// A parallelogram with a corner at `corner` and the edges `edge_u` and `edge_v` from there, which
+// is a rectangle when the edges are at right angles. Facing `edge_u × edge_v`, and lights like the
+// rectangular panels of a ceiling are sampled like triangles.
+#[derive(Clone)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, color: Color) -> Quad {
+        Quad::with_material(corner, edge_u, edge_v, material::lambertian(color))
+    }
+
+    pub fn emissive(corner: Vec3, edge_u: Vec3, edge_v: Vec3, radiance: Color) -> Quad {
+        Quad::with_material(corner, edge_u, edge_v, material::emissive(radiance))
+    }
+
+    pub fn with_material(corner: Vec3, edge_u: Vec3, edge_v: Vec3, material: Arc<dyn Material>) -> Quad {
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+            material,
+        }
+    }
+
+    // The axis-aligned rectangle between two opposite corners, which have one coordinate in common.
+    // It faces the positive direction of that axis.
+    pub fn rect(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> Quad {
+        let size = b - a;
+        let (edge_u, edge_v) = if size.x == 0.0 {
+            (Vec3::new(0.0, size.y, 0.0), Vec3::new(0.0, 0.0, size.z))
+        } else if size.y == 0.0 {
+            (Vec3::new(0.0, 0.0, size.z), Vec3::new(size.x, 0.0, 0.0))
+        } else {
+            assert!(size.z == 0.0, "the corners of a rectangle should have a coordinate in common");
+            (Vec3::new(size.x, 0.0, 0.0), Vec3::new(0.0, size.y, 0.0))
+        };
+        // The edges point the other way if the corners were given the other way around.
+        let axis = edge_u.cross(edge_v);
+        if axis.x + axis.y + axis.z < 0.0 {
+            Quad::with_material(a + edge_u, edge_u.invert(), edge_v, material)
+        } else {
+            Quad::with_material(a, edge_u, edge_v, material)
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.edge_u.cross(self.edge_v).normalize()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.corner + (self.edge_u + self.edge_v) / 2.0
+    }
+}
+
+impl Intersectable for Quad {
+    // The texture coordinates go from 0 to 1 along both edges.
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let cross = self.edge_u.cross(self.edge_v);
+        let delta = intersect_plane(ray, self.corner, cross, t_min, t_max)?;
+        let point = ray.point_along_direction(delta);
+        // The coordinates of the point along the edges, see "Ray Tracing: The Next Week" (Shirley
+        // 2020).
+        let w = cross / cross.dot(cross);
+        let offset = point - self.corner;
+        let u = w.dot(offset.cross(self.edge_v));
+        let v = w.dot(self.edge_u.cross(offset));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        Some(Intersection::new(delta, point, cross.normalize(), Box::new(self.clone())).with_uv((u, v)))
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn is_samplable(&self) -> bool {
+        true
+    }
+
+    fn sample_towards(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let (s, t) = sampler.next_2d();
+        Some(self.corner + s * self.edge_u + t * self.edge_v - origin)
+    }
+
+    fn pdf_towards(&self, origin: Vec3, direction: Vec3) -> f64 {
+        pdf_per_area(self, origin, direction, self.edge_u.cross(self.edge_v).length())
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        Box::new(Quad { corner: self.corner + vec - self.center(), ..self.clone() })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&[self.corner,
+                                 self.corner + self.edge_u,
+                                 self.corner + self.edge_v,
+                                 self.corner + self.edge_u + self.edge_v]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("quad");
+        hasher.write_vec3(self.corner);
+        hasher.write_vec3(self.edge_u);
+        hasher.write_vec3(self.edge_v);
+        self.material.hash(hasher);
+    }
+}
+
+// Where the ray meets the plane through `point` that is at right angles to `normal`, which does
+// not have to be normalized. Rays along the plane miss it.
+fn intersect_plane(ray: &Ray, point: Vec3, normal: Vec3, t_min: f64, t_max: f64) -> Option<f64> {
+    let denominator = normal.dot(ray.direction);
+    if denominator.abs() < EPSILON * normal.length() * ray.direction.length() {
+        return None;
+    }
+    let delta = normal.dot(point - ray.origin) / denominator;
+    if delta < t_max && delta > t_min {
+        Some(delta)
+    } else {
+        None
+    }
+}
+
+// Converts the density per area of a shape sampled uniformly, 1 / area, to a density per solid
+// angle seen from `origin`.
+fn pdf_per_area(shape: &dyn Intersectable, origin: Vec3, direction: Vec3, area: f64) -> f64 {
+    let direction = direction.normalize();
+    match shape.intersects(&Ray::new(origin, direction), 0.0, f64::MAX) {
+        Some(intersection) => {
+            let cosine = direction.dot(intersection.normal).abs();
+            intersection.distance * intersection.distance / (cosine * area)
+        }
+        None => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use hamcrest::prelude::*;
+    use material;
+    use prelude::*;
+    use tests::down;
+
+    #[test]
+    fn ray_should_hit_a_plane_anywhere_but_along_it() {
+        let floor = Plane::new(Vec3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 2.0, 0.0), Color::white());
+
+        let i = floor.intersects(&down(1000.0, -3.5), 0.0, f64::MAX).unwrap();
+        let along = Ray::new(Vec3::new(0.0, -0.5, 1.0), Vec3::new(1.0, 0.0, 0.0));
+
+        assert_that!(i.distance, is(equal_to(5.5)));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+        assert_that!(i.uv.is_some(), is(true));
+        assert_that!(floor.intersects(&along, 0.0, f64::MAX).is_none(), is(true));
+        assert_that!(floor.aabb().is_none(), is(true));
+    }
+
+    #[test]
+    fn ray_should_only_hit_a_disk_within_its_radius() {
+        let disk = Disk::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, Color::white());
+
+        let center = disk.intersects(&down(0.0, 0.0), 0.0, f64::MAX).unwrap();
+        let rim = disk.intersects(&down(0.0, 0.5), 0.0, f64::MAX).unwrap();
+
+        assert_that!(center.distance, is(equal_to(5.0)));
+        assert_that!(rim.uv.unwrap().1, is(close_to(0.5, 1e-12)));
+        assert_that!(disk.intersects(&down(0.8, 0.8), 0.0, f64::MAX).is_none(), is(true));
+        let aabb = disk.aabb().unwrap();
+        assert_that!(aabb.max, is(equal_to(Vec3::new(1.0, 0.0, 1.0))));
+    }
+
+    #[test]
+    fn ray_should_hit_a_quad_between_its_edges() {
+        let quad = Quad::new(Vec3::new(-1.0, 0.0, -1.0),
+                             Vec3::new(0.0, 0.0, 2.0),
+                             Vec3::new(4.0, 0.0, 0.0),
+                             Color::white());
+
+        let i = quad.intersects(&down(1.0, 0.5), 0.0, f64::MAX).unwrap();
+
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+        assert_that!(i.uv, is(equal_to(Some((0.75, 0.5)))));
+        assert_that!(quad.intersects(&down(3.5, 0.0), 0.0, f64::MAX).is_none(), is(true));
+        assert_that!(quad.intersects(&down(0.0, 1.5), 0.0, f64::MAX).is_none(), is(true));
+    }
+
+    #[test]
+    fn rect_should_face_the_positive_axis() {
+        for &(a, b) in [(Vec3::new(-1.0, 2.0, -1.0), Vec3::new(1.0, 2.0, 1.0)),
+                        (Vec3::new(1.0, 2.0, -1.0), Vec3::new(-1.0, 2.0, 1.0))]
+            .iter() {
+            let rect = Quad::rect(a, b, material::lambertian(Color::white()));
+
+            assert_that!(rect.normal(), is(equal_to(Vec3::new(0.0, 1.0, 0.0))));
+            assert_that!(rect.center(), is(equal_to(Vec3::new(0.0, 2.0, 0.0))));
+        }
+    }
+
+    #[test]
+    fn sampled_directions_should_hit_the_light() {
+        let origin = Vec3::new(0.2, -1.0, 0.1);
+        let disk = Disk::emissive(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.5, Color::white());
+        let quad = Quad::emissive(Vec3::new(-0.5, 0.0, -0.5),
+                                  Vec3::new(1.0, 0.0, 0.0),
+                                  Vec3::new(0.0, 0.0, 1.0),
+                                  Color::white());
+        let lights: Vec<Box<dyn Intersectable>> = vec![Box::new(disk), Box::new(quad)];
+
+        let mut sampler = RandomSampler::new(0);
+        for light in lights.iter() {
+            for _ in 0..100 {
+                let direction = light.sample_towards(origin, &mut sampler).unwrap();
+                assert_that!(light.intersects(&Ray::new(origin, direction), 0.0, 1000.0).is_some(),
+                             is(true));
+            }
+        }
+        let straight_up = Vec3::new(0.0, 1.0, 0.0);
+        let disk_pdf = lights[0].pdf_towards(Vec3::new(0.0, -1.0, 0.0), straight_up);
+        assert_that!(disk_pdf, is(close_to(1.0 / (PI * 0.25), 1e-12)));
+        assert_that!(lights[1].pdf_towards(Vec3::new(0.0, -2.0, 0.0), straight_up), is(close_to(4.0, 1e-12)));
+    }
+}
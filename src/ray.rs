@@ -9,13 +9,12 @@ pub struct Ray {
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
         Ray {
-            origin: origin,
-            direction: direction,
+            origin,
+            direction,
         }
     }
 
     pub fn point_along_direction(&self, delta: f64) -> Vec3 {
-        panic!("Step 3a) Calculate the point along the direction of the ray. Hint: Remember to \
-                take the origin of the ray into the account of the final point")
+        self.origin + delta * self.direction
     }
 }
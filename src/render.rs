@@ -0,0 +1,549 @@
+use rayon::{Configuration, ThreadPool};
+use rayon::prelude::*;
+
+use film::{FilmPixel, RunningStats};
+use filter::Filter;
+use integrator::Integrator;
+use sampler::SamplerKind;
+use prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub num_samples: u32,
+    // The number of threads that render tiles, `None` uses one per core.
+    pub num_threads: Option<usize>,
+    // The width and height of the square tiles the image is split into.
+    pub tile_size: u32,
+    pub sampler: SamplerKind,
+    // How the samples are spread over the pixels around them.
+    pub filter: Filter,
+    // Stop sampling pixels that have converged, `num_samples` is then the most a pixel gets in a
+    // pass.
+    pub adaptive: Option<Adaptive>,
+    // Renders with the same seed and frame are identical.
+    pub seed: u64,
+    pub frame: u32,
+}
+
+impl RenderSettings {
+    pub fn new(width: u32, height: u32, num_samples: u32) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            num_samples,
+            num_threads: None,
+            tile_size: 16,
+            sampler: SamplerKind::Sobol,
+            filter: Filter::default(),
+            adaptive: None,
+            seed: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn with_num_threads(mut self, num_threads: usize) -> RenderSettings {
+        self.num_threads = Some(num_threads.max(1));
+        self
+    }
+
+    pub fn with_tile_size(mut self, tile_size: u32) -> RenderSettings {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> RenderSettings {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> RenderSettings {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_adaptive(mut self, min_samples: u32, threshold: f64) -> RenderSettings {
+        self.adaptive = Some(Adaptive {
+            min_samples: min_samples.max(2),
+            threshold,
+        });
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> RenderSettings {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_frame(mut self, frame: u32) -> RenderSettings {
+        self.frame = frame;
+        self
+    }
+}
+
+// A pixel has converged once it has at least `min_samples` samples, and the standard error of
+// its mean luminance is at most `threshold` times the mean.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adaptive {
+    pub min_samples: u32,
+    pub threshold: f64,
+}
+
+impl Adaptive {
+    fn has_converged(&self, luminance: &RunningStats) -> bool {
+        luminance.count >= self.min_samples as u64 &&
+        luminance.standard_error() <= self.threshold * luminance.mean.max(MIN_LUMINANCE)
+    }
+}
+
+// Keeps dark pixels from needing an endless number of samples to reach a relative error.
+const MIN_LUMINANCE: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+// Renders the tiles of the image in parallel. Every pixel has its own sampler, so the image is
+// the same no matter how many threads render it.
+pub fn render(settings: &RenderSettings,
+              camera: &Camera,
+              scene: &Scene,
+              integrator: &dyn Integrator)
+              -> Vec<Color> {
+    render_with_sample_counts(settings, camera, scene, integrator).0
+}
+
+// Like `render`, but also tells how many samples every pixel got.
+pub fn render_with_sample_counts(settings: &RenderSettings,
+                                 camera: &Camera,
+                                 scene: &Scene,
+                                 integrator: &dyn Integrator)
+                                 -> (Vec<Color>, Vec<u32>) {
+    let mut film = Film::new(settings.width, settings.height);
+    render_pass(settings, &mut film, camera, scene, integrator);
+    (film.image(), film.sample_counts())
+}
+
+// The threads that render the tiles, as many as `num_threads` of the settings asks for. Starting
+// threads takes a while, so a caller that renders many passes or frames makes them once and hands
+// them to `render_pass_on` every time.
+pub struct RenderThreads {
+    pool: Option<ThreadPool>,
+}
+
+impl RenderThreads {
+    pub fn new(settings: &RenderSettings) -> RenderThreads {
+        let pool = settings.num_threads.map(|num_threads| {
+            ThreadPool::new(Configuration::new().set_num_threads(num_threads))
+                .expect("could not start the render threads")
+        });
+        RenderThreads { pool }
+    }
+
+    fn install<R: Send, F: FnOnce() -> R + Send>(&self, render: F) -> R {
+        match self.pool {
+            Some(ref pool) => pool.install(render),
+            None => render(),
+        }
+    }
+}
+
+// Adds (up to) `num_samples` samples to every pixel of the film. The samplers carry on where the
+// previous pass stopped rather than repeating its samples, so every pass improves the image, and
+// the film can be looked at in between.
+pub fn render_pass(settings: &RenderSettings,
+                   film: &mut Film,
+                   camera: &Camera,
+                   scene: &Scene,
+                   integrator: &dyn Integrator) {
+    render_pass_on(&RenderThreads::new(settings), settings, film, camera, scene, integrator)
+}
+
+// Like `render_pass`, on threads that were started before.
+pub fn render_pass_on(threads: &RenderThreads,
+                      settings: &RenderSettings,
+                      film: &mut Film,
+                      camera: &Camera,
+                      scene: &Scene,
+                      integrator: &dyn Integrator) {
+    assert!(film.width == settings.width && film.height == settings.height,
+            "the film is {}x{}, but the image is {}x{}",
+            film.width,
+            film.height,
+            settings.width,
+            settings.height);
+    let tiles = tiles(settings);
+    let rendered = {
+        let film = &*film;
+        threads.install(|| {
+            let mut rendered = Vec::with_capacity(tiles.len());
+            tiles.par_iter()
+                .map(|tile| render_tile(settings, film, tile, camera, scene, integrator))
+                .collect_into(&mut rendered);
+            rendered
+        })
+    };
+
+    // The tiles overlap where samples were splatted across their edges, so they are added to the
+    // film one after the other, in the same order no matter which thread rendered them.
+    for (region, region_pixels, region_aovs) in rendered {
+        for (i, pixel) in region_pixels.iter().enumerate() {
+            let (x, y) = (region.x + i as u32 % region.width, region.y + i as u32 / region.width);
+            film.merge_pixel(x, y, pixel);
+            if let Some(aov) = region_aovs.get(i) {
+                film.merge_aov(x, y, aov);
+            }
+        }
+    }
+    film.passes += 1;
+}
+
+// Shows how many samples the pixels got, from blue for the fewest through green to red for the
+// most. The colors are the ones to show, save them with `save_raw_image`.
+pub fn sample_heatmap(sample_counts: &[u32]) -> Vec<Color> {
+    let min = sample_counts.iter().cloned().min().unwrap_or(0) as f64;
+    let max = sample_counts.iter().cloned().max().unwrap_or(0) as f64;
+    sample_counts.iter()
+        .map(|&count| {
+            let t = if max > min { (count as f64 - min) / (max - min) } else { 0.0 };
+            Color::new((2.0 * t - 1.0).max(0.0),
+                       1.0 - (2.0 * t - 1.0).abs(),
+                       (1.0 - 2.0 * t).max(0.0))
+        })
+        .collect()
+}
+
+fn tiles(settings: &RenderSettings) -> Vec<Tile> {
+    let size = settings.tile_size;
+    let mut tiles = Vec::new();
+    for y in (0..settings.height).step_by(size as usize) {
+        for x in (0..settings.width).step_by(size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.min(settings.width - x),
+                height: size.min(settings.height - y),
+            });
+        }
+    }
+    tiles
+}
+
+// Renders the pixels of a tile, and returns the samples they add to the film (and to its output
+// variables, if it has them). The filter spreads samples beyond the edges of the tile, so the
+// returned region is the tile with a border around it.
+fn render_tile(settings: &RenderSettings,
+               film: &Film,
+               tile: &Tile,
+               camera: &Camera,
+               scene: &Scene,
+               integrator: &dyn Integrator)
+               -> (Tile, Vec<FilmPixel>, Vec<AovPixel>) {
+    let (width, height) = (settings.width as f64, settings.height as f64);
+    let radius = settings.filter.radius();
+    let border = radius.ceil() as u32;
+    let region_x = tile.x.saturating_sub(border);
+    let region_y = tile.y.saturating_sub(border);
+    let region = Tile {
+        x: region_x,
+        y: region_y,
+        width: (tile.x + tile.width + border).min(settings.width) - region_x,
+        height: (tile.y + tile.height + border).min(settings.height) - region_y,
+    };
+    let mut pixels = vec![FilmPixel::new(); (region.width * region.height) as usize];
+    let mut aovs = if film.has_aovs() { vec![AovPixel::new(); pixels.len()] } else { Vec::new() };
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            let previous = film.pixel(x, y);
+            let mut sampler = settings.sampler
+                .for_pixel(settings.seed, settings.frame, x, y, settings.num_samples);
+            let mut luminance = previous.luminance;
+            let mut num_samples = 0;
+            while num_samples < settings.num_samples {
+                if settings.adaptive.is_some_and(|adaptive| adaptive.has_converged(&luminance)) {
+                    break;
+                }
+                sampler.start_sample(previous.num_samples + num_samples);
+                num_samples += 1;
+                let (jitter_x, jitter_y) = sampler.next_2d();
+                let u = (x as f64 + jitter_x) / width;
+                let v = ((height - y as f64 - 1.0) + jitter_y) / height;
+
+                let ray = camera.create_ray(u, v, &mut *sampler);
+                let own_index = ((y - region.y) * region.width + x - region.x) as usize;
+                let (color, direct, indirect) = if film.has_aovs() {
+                    let (direct, indirect) = integrator.direct_and_indirect(&ray, scene, &mut *sampler);
+                    add_first_hit(&mut aovs[own_index], &ray, scene);
+                    (direct + indirect, direct, indirect)
+                } else {
+                    let color = integrator.radiance(&ray, scene, &mut *sampler);
+                    (color, Color::black(), Color::black())
+                };
+                luminance.add(color.luminance());
+                pixels[own_index].count_sample(color);
+
+                // The position of the sample on the film, counting rows upwards like `v` does.
+                let (film_x, film_row) = (x as f64 + jitter_x, (settings.height - y - 1) as f64 + jitter_y);
+                let first_row = settings.height - region.y - region.height;
+                for splat_row in pixels_within(film_row, radius, first_row, region.height) {
+                    let splat_y = settings.height - splat_row - 1;
+                    for splat_x in pixels_within(film_x, radius, region.x, region.width) {
+                        let weight = settings.filter.weight(splat_x as f64 + 0.5 - film_x,
+                                                            splat_row as f64 + 0.5 - film_row);
+                        let index = ((splat_y - region.y) * region.width + splat_x - region.x) as usize;
+                        pixels[index].splat(color, weight);
+                        if let Some(aov) = aovs.get_mut(index) {
+                            aov.direct = aov.direct + direct * weight;
+                            aov.indirect = aov.indirect + indirect * weight;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    (region, pixels, aovs)
+}
+
+// Adds what the camera ray hits first to the output variables of its pixel.
+fn add_first_hit(aov: &mut AovPixel, ray: &Ray, scene: &Scene) {
+    if let Some((shape, intersection)) = scene.intersects_shape(ray, 0.0, f64::MAX) {
+        aov.normal = aov.normal + intersection.normal.normalize();
+        aov.depth += intersection.distance * ray.direction.length();
+        aov.position = aov.position + intersection.intersection_point;
+        if aov.hits == 0 {
+            aov.shape = Some(shape as u32);
+            aov.material = intersection.shape
+                .material()
+                .and_then(|material| scene.material_id(material))
+                .map(|id| id as u32);
+        }
+        aov.hits += 1;
+        if let Some(material) = intersection.shape.material() {
+            aov.albedo = aov.albedo + material.albedo(&intersection);
+        }
+    }
+}
+
+// The pixels in `start..start + length` with centers in `(position - radius, position + radius]`.
+fn pixels_within(position: f64, radius: f64, start: u32, length: u32) -> ::std::ops::Range<u32> {
+    let first = ((position - 0.5 - radius).floor() + 1.0).max(start as f64) as u32;
+    let last = ((position - 0.5 + radius).floor() + 1.0).min((start + length) as f64).max(first as f64);
+    first..last as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::*;
+
+    fn camera(settings: &RenderSettings) -> Camera {
+        Camera::new(Vec3::new(0.0, 0.5, 2.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                    Vec3::new(0.0, 1.0, 0.0),
+                    40.0,
+                    settings.width as f64 / settings.height as f64,
+                    0.1,
+                    3.0)
+    }
+
+    fn scene() -> Scene {
+        Scene::new(vec![Box::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Color::red())),
+                        Box::new(Sphere::refractive(Vec3::new(0.6, 0.0, -0.5), 0.3, Color::white(), 1.5)),
+                        Box::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, Color::white()))])
+    }
+
+    #[test]
+    fn image_should_not_depend_on_the_number_of_threads() {
+        // Mitchell spreads samples over the edges of the tiles, which are added up in a fixed order.
+        let settings = RenderSettings::new(37, 21, 2)
+            .with_tile_size(8)
+            .with_filter(Filter::from_name("mitchell").unwrap());
+        let (camera, scene) = (camera(&settings), scene());
+
+        let one = render(&settings.with_num_threads(1), &camera, &scene, &PathTracer::new());
+        let four = render(&settings.with_num_threads(4), &camera, &scene, &PathTracer::new());
+
+        assert_that!(one.len(), is(equal_to(37 * 21)));
+        assert_that!(one, is(equal_to(four)));
+    }
+
+    #[test]
+    fn threads_should_be_reused_for_every_pass() {
+        let settings = RenderSettings::new(8, 8, 2).with_num_threads(3);
+        let (camera, scene) = (camera(&settings), scene());
+        let threads = RenderThreads::new(&settings);
+        let (mut reused, mut started) = (Film::new(8, 8), Film::new(8, 8));
+
+        for _ in 0..3 {
+            render_pass_on(&threads, &settings, &mut reused, &camera, &scene, &PathTracer::new());
+            render_pass(&settings, &mut started, &camera, &scene, &PathTracer::new());
+        }
+
+        assert_that!(reused, is(equal_to(started)));
+    }
+
+    #[test]
+    fn image_should_depend_on_the_seed_and_frame() {
+        let settings = RenderSettings::new(8, 8, 1);
+        let (camera, scene) = (camera(&settings), scene());
+        let image = |settings: RenderSettings| render(&settings, &camera, &scene, &PathTracer::new());
+
+        assert_that!(image(settings), is(equal_to(image(settings))));
+        assert_that!(image(settings) == image(settings.with_seed(1)), is(false));
+        assert_that!(image(settings) == image(settings.with_frame(1)), is(false));
+    }
+
+    fn mean_squared_error(image: &[Color], reference: &[Color]) -> f64 {
+        let sum: f64 = image.iter()
+            .zip(reference)
+            .map(|(a, b)| {
+                let (r, g, b) = (a.r - b.r, a.g - b.g, a.b - b.b);
+                r * r + g * g + b * b
+            })
+            .sum();
+        sum / image.len() as f64
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_should_have_less_error_than_independent_samples() {
+        let settings = RenderSettings::new(16, 16, 64);
+        let (camera, scene) = (camera(&settings), scene());
+        let reference = render(&RenderSettings { num_samples: 4096, ..settings }.with_seed(99),
+                               &camera,
+                               &scene,
+                               &PathTracer::new());
+        let error = |sampler| {
+            let image = render(&settings.with_sampler(sampler), &camera, &scene, &PathTracer::new());
+            mean_squared_error(&image, &reference)
+        };
+
+        let independent = error(SamplerKind::Independent);
+        for &sampler in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol].iter() {
+            assert_that!(error(sampler), is(less_than(0.6 * independent)));
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_should_stop_where_the_pixel_has_converged() {
+        let settings = RenderSettings::new(16, 16, 256).with_adaptive(8, 0.02);
+        let (camera, scene) = (camera(&settings), scene());
+
+        let (_, counts) = render_with_sample_counts(&settings, &camera, &scene, &PathTracer::new());
+
+        // The top row only sees the sky, which has no noise.
+        assert_that!(counts[..16].iter().all(|&count| count == 8), is(true));
+        assert_that!(*counts.iter().max().unwrap(), is(equal_to(256)));
+    }
+
+    #[test]
+    fn passes_should_add_up_to_a_single_render() {
+        for kind in SamplerKind::NAMES.iter().map(|name| SamplerKind::from_name(name).unwrap()) {
+            let settings = RenderSettings::new(8, 8, 4).with_sampler(kind);
+            let (camera, scene) = (camera(&settings), scene());
+            let mut film = Film::new(8, 8);
+
+            render_pass(&settings, &mut film, &camera, &scene, &PathTracer::new());
+            let preview = film.image();
+            render_pass(&settings, &mut film, &camera, &scene, &PathTracer::new());
+
+            assert_that!(film.passes, is(equal_to(2)));
+            assert_that!(film.sample_counts(), is(equal_to(vec![8; 64])));
+            // A pass that repeated the samples of the one before would leave the image as it was.
+            assert_that!(film.image() == preview, is(false));
+            // The strata of the stratified sampler are as many as the samples of a pass, so its
+            // passes of 4 samples are not the same as a render of 8.
+            if kind == SamplerKind::Stratified {
+                continue;
+            }
+            let single = RenderSettings { num_samples: 8, ..settings };
+            for (a, b) in film.image().iter().zip(render(&single, &camera, &scene, &PathTracer::new())) {
+                assert_that!(a.r, is(close_to(b.r, 1e-9)));
+                assert_that!(a.g, is(close_to(b.g, 1e-9)));
+                assert_that!(a.b, is(close_to(b.b, 1e-9)));
+            }
+        }
+    }
+
+    #[test]
+    fn box_filter_should_keep_samples_in_their_own_pixel() {
+        for &jitter in [0.0, 0.25, 0.999].iter() {
+            assert_that!(pixels_within(3.0 + jitter, 0.5, 0, 8), is(equal_to(3..4)));
+        }
+        assert_that!(pixels_within(3.5, 2.0, 0, 8), is(equal_to(2..6)));
+        assert_that!(pixels_within(0.5, 2.0, 0, 8), is(equal_to(0..3)));
+    }
+
+    #[test]
+    fn wider_filters_should_blur_the_image() {
+        let settings = RenderSettings::new(16, 16, 16);
+        let (camera, scene) = (camera(&settings), scene());
+        let image = |filter| render(&settings.with_filter(filter), &camera, &scene, &PathTracer::new());
+        // The difference between neighbouring pixels.
+        let contrast = |image: Vec<Color>| -> f64 {
+            image.windows(2).map(|pair| (pair[0].r - pair[1].r).abs()).sum()
+        };
+
+        let sharp = contrast(image(Filter::default()));
+        let blurred = contrast(image(Filter::from_name("gaussian").unwrap()));
+
+        assert_that!(blurred, is(less_than(sharp)));
+    }
+
+    #[test]
+    fn output_variables_should_not_change_the_image() {
+        let settings = RenderSettings::new(8, 8, 4);
+        let (camera, scene) = (camera(&settings), scene());
+        let mut film = Film::new(8, 8).with_aovs();
+
+        render_pass(&settings, &mut film, &camera, &scene, &PathTracer::new());
+
+        let layers = film.layers();
+        let names: Vec<&str> = layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_that!(names,
+                     is(equal_to(vec!["",
+                                      "albedo",
+                                      "normal",
+                                      "depth",
+                                      "position",
+                                      "shape",
+                                      "material",
+                                      "samples",
+                                      "direct",
+                                      "indirect"])));
+        assert_that!(film.image(), is(equal_to(render(&settings, &camera, &scene, &PathTracer::new()))));
+        // The direct and indirect light add up to the radiance.
+        for ((beauty, direct), indirect) in layers[0].values.iter().zip(&layers[8].values).zip(&layers[9].values) {
+            assert_that!((direct + indirect) as f64, is(close_to(*beauty as f64, 1e-5)));
+        }
+        // The top row sees the sky, the bottom row the floor right below the camera.
+        assert_that!(layers[3].values[0], is(equal_to(f32::INFINITY)));
+        assert_that!(layers[2].values[3 * 60 + 1], is(close_to(1.0, 1e-3)));
+        assert_that!(layers[5].values[0], is(equal_to(-1.0)));
+        assert_that!(layers[5].values[60], is(equal_to(2.0)));
+        assert_that!(layers[6].values[60], is(equal_to(2.0)));
+        // The top of the floor is at y = -0.5.
+        assert_that!(layers[4].values[3 * 60 + 1] as f64, is(close_to(-0.5, 1e-2)));
+    }
+
+    #[test]
+    fn heatmap_should_go_from_blue_to_red() {
+        let heatmap = sample_heatmap(&[4, 6, 8]);
+
+        assert_that!(heatmap, is(equal_to(vec![Color::blue(), Color::green(), Color::red()])));
+    }
+
+    #[test]
+    fn tiles_should_cover_the_image_once() {
+        let settings = RenderSettings::new(37, 21, 1).with_tile_size(8);
+
+        let area: u32 = tiles(&settings).iter().map(|tile| tile.width * tile.height).sum();
+
+        assert_that!(area, is(equal_to(37 * 21)));
+    }
+}
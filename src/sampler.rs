@@ -0,0 +1,465 @@
+use std::f64;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+const PRIMES: [u32; 32] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67,
+                           71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131];
+
+// The source of every random decision made while rendering: where in a pixel a ray starts, where
+// it passes through the lens and how it scatters. Samplers that start from the same seed make the
+// same decisions, so a render can be reproduced.
+//
+// Every number a sample asks for is a dimension of the sample, the first being the position in
+// the pixel. Low-discrepancy samplers spread each dimension evenly over the samples of a pixel.
+pub trait Sampler {
+    // Starts sample `index` of the pixel, the following numbers are its dimensions.
+    fn start_sample(&mut self, _: u32) {}
+
+    // A number in [0, 1).
+    fn next_f64(&mut self) -> f64;
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let a = self.next_f64();
+        (a, self.next_f64())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    // The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    // The sampler of a pixel in a frame of an animation that takes `num_samples` samples. Every
+    // pixel gets its own numbers, so the image does not depend on the order the pixels are
+    // rendered in.
+    pub fn for_pixel(&self, seed: u64, frame: u32, x: u32, y: u32, num_samples: u32) -> Box<dyn Sampler> {
+        let seed = pixel_seed(seed, frame, x, y);
+        match *self {
+            SamplerKind::Independent => Box::new(RandomSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, num_samples)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+// Independent, uniformly distributed random numbers. Every sample of a pixel starts from its own
+// seed, so a pass that carries on where the previous one stopped gets new numbers.
+pub struct RandomSampler {
+    seed: u64,
+    rng: XorShiftRng,
+}
+
+impl RandomSampler {
+    pub fn new(seed: u64) -> RandomSampler {
+        RandomSampler { seed, rng: xor_shift(seed) }
+    }
+
+    pub fn for_pixel(seed: u64, frame: u32, x: u32, y: u32) -> RandomSampler {
+        RandomSampler::new(pixel_seed(seed, frame, x, y))
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn start_sample(&mut self, index: u32) {
+        self.rng = xor_shift(mix(self.seed ^ mix(index as u64)));
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+}
+
+// Splits every dimension into one stratum per sample, and picks a random point in a stratum.
+// Pairs of dimensions are stratified in both directions at once with correlated multi-jittered
+// sampling, see "Correlated Multi-Jittered Sampling" (Kensler 2013). The strata are shuffled
+// differently for every dimension, so the dimensions do not correlate. Samples beyond
+// `num_samples` start new sets of strata, shuffled differently, so a pixel can keep sampling.
+pub struct StratifiedSampler {
+    num_samples: u32,
+    set: u32,
+    sample: u32,
+    dimension: u32,
+    seed: u32,
+    jitter: RandomSampler,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, num_samples: u32) -> StratifiedSampler {
+        StratifiedSampler {
+            num_samples: num_samples.max(1),
+            set: 0,
+            sample: 0,
+            dimension: 0,
+            seed: mix(seed) as u32,
+            jitter: RandomSampler::new(seed),
+        }
+    }
+
+    fn next_key(&mut self) -> u32 {
+        self.dimension += 1;
+        hash(self.seed ^ self.set.wrapping_mul(0x9e37_79b9), self.dimension)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, index: u32) {
+        self.set = index / self.num_samples;
+        self.sample = index % self.num_samples;
+        self.dimension = 0;
+        self.jitter.start_sample(index);
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        let n = self.num_samples;
+        let stratum = permute(self.sample, n, self.next_key());
+        ((stratum as f64 + self.jitter.next_f64()) / n as f64).min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let n = self.num_samples;
+        let key = self.next_key();
+        let columns = (n as f64).sqrt() as u32;
+        let rows = n.div_ceil(columns);
+        let s = permute(self.sample, n, key.wrapping_mul(0x5163_3e2d));
+        let (column, row) = (s % columns, s / columns);
+        let sx = permute(column, columns, key.wrapping_mul(0xa511_e9b3));
+        let sy = permute(row, rows, key.wrapping_mul(0x63d8_3595));
+        let (jx, jy) = self.jitter.next_2d();
+        let x = (column as f64 + (sy as f64 + jx) / rows as f64) / columns as f64;
+        let y = (row as f64 + (sx as f64 + jy) / columns as f64) / rows as f64;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+// The Halton sequence uses the radical inverse in a prime base for every dimension. Every pixel
+// shifts the sequence by a random offset (a Cranley-Patterson rotation), and dimensions beyond
+// the table of primes get independent random numbers.
+pub struct HaltonSampler {
+    sample: u32,
+    dimension: u32,
+    seed: u32,
+    fallback: RandomSampler,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            sample: 0,
+            dimension: 0,
+            seed: mix(seed) as u32,
+            fallback: RandomSampler::new(seed),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, index: u32) {
+        self.sample = index;
+        self.dimension = 0;
+        self.fallback.start_sample(index);
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        let dimension = self.dimension as usize;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return self.fallback.next_f64();
+        }
+        let offset = to_f64(hash(self.seed, self.dimension));
+        let value = radical_inverse(PRIMES[dimension], self.sample) + offset;
+        (if value >= 1.0 { value - 1.0 } else { value }).min(ONE_MINUS_EPSILON)
+    }
+}
+
+// A Sobol sequence with Owen scrambling, see "Practical Hash-based Owen Scrambling" (Burley
+// 2020). Every pair of dimensions uses the first two dimensions of the Sobol sequence, which are
+// well distributed together, scrambled and shuffled differently so the pairs do not correlate.
+pub struct SobolSampler {
+    sample: u32,
+    dimension: u32,
+    seed: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            sample: 0,
+            dimension: 0,
+            seed: mix(seed) as u32,
+        }
+    }
+
+    // The shuffled index of the current sample and the seed to scramble its dimensions with.
+    fn next_index(&mut self) -> (u32, u32) {
+        self.dimension += 1;
+        let seed = hash(self.seed, self.dimension);
+        (nested_uniform_scramble(self.sample, seed), seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, index: u32) {
+        self.sample = index;
+        self.dimension = 0;
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        let (index, seed) = self.next_index();
+        to_f64(nested_uniform_scramble(index.reverse_bits(), hash(seed, 0)))
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let (index, seed) = self.next_index();
+        (to_f64(nested_uniform_scramble(index.reverse_bits(), hash(seed, 0))),
+         to_f64(nested_uniform_scramble(sobol_second_dimension(index), hash(seed, 1))))
+    }
+}
+
+// XorShift needs a seed that is not all zeros, and similar seeds (like neighbouring pixels or
+// samples) should not give similar numbers.
+fn xor_shift(seed: u64) -> XorShiftRng {
+    let (a, b) = (mix(seed), mix(seed ^ 0x5555_5555_5555_5555));
+    XorShiftRng::from_seed([a as u32 | 1, (a >> 32) as u32, b as u32, (b >> 32) as u32])
+}
+
+fn pixel_seed(seed: u64, frame: u32, x: u32, y: u32) -> u64 {
+    mix(mix(mix(seed) ^ frame as u64) ^ ((y as u64) << 32 | x as u64))
+}
+
+// The SplitMix64 finalizer, it spreads every bit of the input over the output.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn hash(seed: u32, value: u32) -> u32 {
+    mix((seed as u64) << 32 | value as u64) as u32
+}
+
+fn to_f64(value: u32) -> f64 {
+    value as f64 / 4_294_967_296.0
+}
+
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut value = 0.0;
+    while index > 0 {
+        value += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+// A random permutation of [0, length) that is picked by `key`, without storing it (Kensler 2013).
+fn permute(mut i: u32, length: u32, key: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= key;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= key >> 16;
+        i ^= (i & w) >> 4;
+        i ^= key >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= key >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | key >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            return (i.wrapping_add(key)) % length;
+        }
+    }
+}
+
+// The second dimension of the Sobol sequence, the first one is `index.reverse_bits()`.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// Owen scrambling: every bit is flipped depending on the bits above it (Laine and Karras 2011,
+// with the constants of Burley 2020).
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::SamplerKind;
+
+    const KINDS: [SamplerKind; 4] = [SamplerKind::Independent,
+                                     SamplerKind::Stratified,
+                                     SamplerKind::Halton,
+                                     SamplerKind::Sobol];
+
+    fn numbers(mut sampler: RandomSampler) -> Vec<f64> {
+        (0..16).map(|_| sampler.next_f64()).collect()
+    }
+
+    // The first `dimensions` numbers of `num_samples` samples, by dimension.
+    fn dimensions(kind: SamplerKind, num_samples: u32, dimensions: usize) -> Vec<Vec<f64>> {
+        let mut sampler = kind.for_pixel(3, 0, 5, 7, num_samples);
+        let mut values = vec![Vec::new(); dimensions];
+        for index in 0..num_samples {
+            sampler.start_sample(index);
+            for dimension in values.iter_mut() {
+                dimension.push(sampler.next_f64());
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn samplers_with_the_same_seed_should_make_the_same_decisions() {
+        assert_that!(numbers(RandomSampler::for_pixel(7, 2, 10, 20)),
+                     is(equal_to(numbers(RandomSampler::for_pixel(7, 2, 10, 20)))));
+        for &kind in KINDS.iter() {
+            assert_that!(dimensions(kind, 16, 8), is(equal_to(dimensions(kind, 16, 8))));
+        }
+    }
+
+    #[test]
+    fn pixels_and_frames_should_get_their_own_numbers() {
+        let pixel = numbers(RandomSampler::for_pixel(7, 2, 10, 20));
+
+        assert_that!(pixel == numbers(RandomSampler::for_pixel(7, 2, 11, 20)), is(false));
+        assert_that!(pixel == numbers(RandomSampler::for_pixel(7, 2, 20, 10)), is(false));
+        assert_that!(pixel == numbers(RandomSampler::for_pixel(7, 3, 10, 20)), is(false));
+        assert_that!(pixel == numbers(RandomSampler::for_pixel(8, 2, 10, 20)), is(false));
+    }
+
+    #[test]
+    fn samples_should_not_depend_on_the_samples_before_them() {
+        for &kind in KINDS.iter() {
+            let sample = |sampler: &mut Box<dyn Sampler>, index| -> Vec<f64> {
+                sampler.start_sample(index);
+                (0..40).map(|_| sampler.next_f64()).collect()
+            };
+            let mut first_pass = kind.for_pixel(3, 0, 5, 7, 4);
+            let mut second_pass = kind.for_pixel(3, 0, 5, 7, 4);
+            for index in 0..4 {
+                sample(&mut first_pass, index);
+            }
+
+            // A later pass makes a new sampler, which should carry on with new numbers.
+            let carried_on = sample(&mut first_pass, 4);
+            assert_that!(sample(&mut second_pass, 4), is(equal_to(carried_on.clone())));
+            assert_that!(sample(&mut second_pass, 0) == carried_on, is(false));
+        }
+    }
+
+    #[test]
+    fn samplers_should_give_numbers_between_zero_and_one() {
+        for &kind in KINDS.iter() {
+            for value in dimensions(kind, 64, 40).iter().flat_map(|dimension| dimension.iter()) {
+                assert_that!(*value >= 0.0 && *value < 1.0, is(true));
+            }
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_should_put_one_sample_in_every_stratum() {
+        for &kind in [SamplerKind::Stratified, SamplerKind::Sobol].iter() {
+            for dimension in dimensions(kind, 16, 6) {
+                let mut strata: Vec<usize> = dimension.iter().map(|v| (v * 16.0) as usize).collect();
+                strata.sort();
+                assert_that!(strata, is(equal_to((0..16).collect::<Vec<usize>>())));
+            }
+        }
+    }
+
+    #[test]
+    fn two_dimensional_samples_should_be_stratified_in_both_directions() {
+        for &kind in [SamplerKind::Stratified, SamplerKind::Sobol].iter() {
+            let mut sampler = kind.for_pixel(3, 0, 5, 7, 16);
+            let mut xs = Vec::new();
+            let mut ys = Vec::new();
+            for index in 0..16 {
+                sampler.start_sample(index);
+                let (x, y) = sampler.next_2d();
+                xs.push((x * 16.0) as usize);
+                ys.push((y * 16.0) as usize);
+            }
+            xs.sort();
+            ys.sort();
+            assert_that!(xs, is(equal_to((0..16).collect::<Vec<usize>>())));
+            assert_that!(ys, is(equal_to((0..16).collect::<Vec<usize>>())));
+        }
+    }
+
+    #[test]
+    fn stratified_sampler_should_start_a_new_set_of_strata_after_num_samples() {
+        let mut sampler = SamplerKind::Stratified.for_pixel(3, 0, 5, 7, 16);
+        let mut sets = [Vec::new(), Vec::new()];
+        for index in 0..32 {
+            sampler.start_sample(index);
+            sets[index as usize / 16].push((sampler.next_f64() * 16.0) as usize);
+        }
+
+        // The samples should visit the strata in a different order.
+        assert_that!(sets[0] == sets[1], is(false));
+        for set in sets.iter_mut() {
+            set.sort();
+            assert_that!(set.clone(), is(equal_to((0..16).collect::<Vec<usize>>())));
+        }
+    }
+}
@@ -1,26 +1,41 @@
-use rand::{self, Rng};
-use bmp;
+use std::f64::consts::PI;
 
 use ::vec::Vec3;
 use ::ray::Ray;
 use ::color::Color;
 use ::scene::*;
+use ::sampler::Sampler;
+use ::material::Texture;
 
 const INTERSECTION_ORIGIN_OFFSET: f64 = 0.00000001;
 
-pub fn diffusive(attenuation: Color, intersection: &Intersection) -> Option<(Color, Ray)> {
-    Some((attenuation, scatter_ray(intersection)))
+pub fn diffusive(attenuation: Color,
+                 ray: &Ray,
+                 intersection: &Intersection,
+                 sampler: &mut dyn Sampler)
+                 -> Option<(Color, Ray)> {
+    Some((attenuation, scatter_ray(intersection, facing_normal(ray, intersection), sampler)))
+}
+
+// The probability density (per solid angle) of `diffusive` scattering towards `direction`. The
+// scattered rays follow the cosine of the angle to the normal, so for a diffusive surface this
+// is also the fraction of light that is scattered towards `direction` (divided by the albedo).
+pub fn diffusive_pdf(ray: &Ray, intersection: &Intersection, direction: Vec3) -> f64 {
+    let cosine = facing_normal(ray, intersection).dot(direction.normalize());
+    if cosine > 0.0 { cosine / PI } else { 0.0 }
 }
 
 pub fn reflection(attenuation: Color,
                   diffusiveness: f64,
                   ray: &Ray,
-                  intersection: &Intersection)
+                  intersection: &Intersection,
+                  sampler: &mut dyn Sampler)
                   -> Option<(Color, Ray)> {
-    let reflected = reflect(ray.direction, intersection.normal) +
-                    diffusiveness * random_point_in_unit_sphere();
-    let origin = reflection_origin(intersection);
-    if reflected.dot(intersection.normal) > 0.0 {
+    let normal = facing_normal(ray, intersection);
+    let reflected = reflect(ray.direction, normal) +
+                    diffusiveness * random_point_in_unit_sphere(sampler);
+    let origin = reflection_origin(intersection.intersection_point, normal);
+    if reflected.dot(normal) > 0.0 {
         Some((attenuation, Ray::new(origin, reflected)))
     } else {
         None
@@ -29,7 +44,8 @@ pub fn reflection(attenuation: Color,
 
 pub fn refraction(refraction_index: f64,
                   ray: &Ray,
-                  intersection: &Intersection)
+                  intersection: &Intersection,
+                  sampler: &mut dyn Sampler)
                   -> Option<(Color, Ray)> {
     let attenuation = Color::white();
 
@@ -46,7 +62,7 @@ pub fn refraction(refraction_index: f64,
     let refracted = refract(ray.direction, outward_normal, ni_over_nt);
     let should_refract = refracted.is_some() &&
                          shlick_approximation(cosine, refraction_index) <
-                         rand::thread_rng().next_f64();
+                         sampler.next_f64();
 
     match refracted {
         Some(refracted) if should_refract => {
@@ -54,7 +70,7 @@ pub fn refraction(refraction_index: f64,
             Some((attenuation, Ray::new(origin, refracted)))
         }
         _ => {
-            let origin = reflection_origin(intersection);
+            let origin = reflection_origin(intersection.intersection_point, outward_normal);
             let reflected = reflect(ray.direction, intersection.normal);
             Some((attenuation, Ray::new(origin, reflected)))
         }
@@ -62,33 +78,75 @@ pub fn refraction(refraction_index: f64,
 
 }
 
-pub fn texture(texture: &bmp::Image, intersection: &Intersection) -> Option<(Color, Ray)> {
-    panic!("Step 6b) Calculate the (u, v) coordinates of the surface normal in the intersection, \
-            similarily to how you did it in Step 5. Then, convert the respective pixel from the \
-            texture to a Color. You can use the scatter_ray() function below to calculate the Ray.")
+pub fn texture(texture: &Texture,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)> {
+    diffusive(texture_color(texture, intersection), ray, intersection, sampler)
 }
 
-fn scatter_ray(intersection: &Intersection) -> Ray {
-    let target = intersection.intersection_point + intersection.normal +
-                 random_point_in_unit_sphere();
-    let origin = reflection_origin(intersection);
-    let direction = (target - origin).normalize();
-    Ray::new(origin, direction)
+pub fn texture_color(texture: &Texture, intersection: &Intersection) -> Color {
+    let (u, v) = intersection.uv.unwrap_or_else(|| spherical_uv(intersection.normal));
+    // Coordinates outside of [0, 1] repeat the texture.
+    let (u, v) = (u - u.floor(), v - v.floor());
+    let x = ((u * texture.width as f64) as u32).min(texture.width - 1);
+    let y = (((1.0 - v) * texture.height as f64) as u32).min(texture.height - 1);
+    texture.pixel(x, y)
 }
 
-fn random_point_in_unit_sphere() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = 2.0 * Vec3::new(rng.next_f64(), rng.next_f64(), rng.next_f64()) -
-                Vec3::new(1.0, 1.0, 1.0);
-        if p.squared_length() < 1.0 {
-            return p;
-        }
+// Maps a direction to a point on an image that is wrapped around a sphere, see
+// https://en.wikipedia.org/wiki/UV_mapping
+fn spherical_uv(direction: Vec3) -> (f64, f64) {
+    let d = direction.normalize();
+    let u = 0.5 + d.z.atan2(d.x) / (2.0 * PI);
+    let v = 0.5 + d.y.asin() / PI;
+    (u, v)
+}
+
+// Picks a point uniformly on the unit disc and projects it up onto the hemisphere around the
+// normal, which gives directions that are cosine distributed around it (Malley's method).
+fn scatter_ray(intersection: &Intersection, normal: Vec3, sampler: &mut dyn Sampler) -> Ray {
+    let (u1, u2) = sampler.next_2d();
+    let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    let direction = r * phi.cos() * tangent + r * phi.sin() * bitangent +
+                    (1.0 - u1).max(0.0).sqrt() * normal;
+    let origin = reflection_origin(intersection.intersection_point, normal);
+    Ray::new(origin, direction.normalize())
+}
+
+// A ray from the intersection towards `direction`, starting on the side of the surface that
+// `direction` points to.
+pub fn ray_towards(intersection: &Intersection, direction: Vec3) -> Ray {
+    let normal = if direction.dot(intersection.normal) < 0.0 {
+        intersection.normal.invert()
+    } else {
+        intersection.normal
+    };
+    Ray::new(reflection_origin(intersection.intersection_point, normal), direction)
+}
+
+// Maps three numbers to a uniformly distributed point, instead of rejecting points outside the
+// sphere, so every scattered ray uses the same number of sampler dimensions.
+fn random_point_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.next_2d();
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    sampler.next_f64().cbrt() * Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn facing_normal(ray: &Ray, intersection: &Intersection) -> Vec3 {
+    if ray.direction.dot(intersection.normal) > 0.0 {
+        intersection.normal.invert()
+    } else {
+        intersection.normal
     }
 }
 
-fn reflection_origin(intersection: &Intersection) -> Vec3 {
-    intersection.intersection_point + intersection.normal * INTERSECTION_ORIGIN_OFFSET
+fn reflection_origin(origin: Vec3, normal: Vec3) -> Vec3 {
+    origin + normal * INTERSECTION_ORIGIN_OFFSET
 }
 
 fn reflect(v: Vec3, n: Vec3) -> Vec3 {
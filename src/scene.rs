@@ -1,115 +1,301 @@
-use scatter;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use bmp;
+
+use aabb::Aabb;
+use bvh::Bvh;
+use material::{self, Material, Textured};
 use prelude::*;
 
-pub trait Intersectable {
+// Shapes are shared between the threads that render the image.
+pub trait Intersectable: Send + Sync {
     fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection>;
 
-    fn scatter(&self, _: &Ray, _: &Intersection) -> Option<(Color, Ray)> {
+    // The material of the shape, shapes without one absorb every ray.
+    fn material(&self) -> Option<&dyn Material> {
+        None
+    }
+
+    // Every material the shape can scatter with, shapes made of parts can have more than one.
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.material().into_iter().collect()
+    }
+
+    fn scatter(&self,
+               ray: &Ray,
+               intersection: &Intersection,
+               sampler: &mut dyn Sampler)
+               -> Option<(Color, Ray)> {
+        self.material().and_then(|material| material.scatter(ray, intersection, sampler))
+    }
+
+    fn emitted(&self, ray: &Ray, intersection: &Intersection) -> Color {
+        self.material().map_or(Color::black(), |material| material.emitted(ray, intersection))
+    }
+
+    // Whether `sample_towards` picks directions towards the shape, only emissive shapes that do
+    // are sampled as lights.
+    fn is_samplable(&self) -> bool {
+        false
+    }
+
+    // A direction from `origin` towards a random point on the shape, used to send shadow rays
+    // towards lights. Shapes that can not be sampled return `None`.
+    fn sample_towards(&self, _: Vec3, _: &mut dyn Sampler) -> Option<Vec3> {
         None
     }
 
-    fn move_to(&self, vec: Vec3) -> Box<Intersectable>;
+    // The probability density (per solid angle) of `sample_towards` picking `direction`.
+    fn pdf_towards(&self, _: Vec3, _: Vec3) -> f64 {
+        0.0
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable>;
+
+    // The bounds of the shape, or `None` if it is unbounded.
+    fn aabb(&self) -> Option<Aabb>;
+
+    // Feeds everything that decides how the shape looks to the hasher, its materials included,
+    // so `scene_hash` changes whenever the shape does.
+    fn hash(&self, hasher: &mut SceneHasher);
 }
 
 pub struct Intersection {
     pub distance: f64,
     pub intersection_point: Vec3,
     pub normal: Vec3,
-    pub shape: Box<Intersectable>,
+    pub shape: Box<dyn Intersectable>,
+    pub barycentric: Option<(f64, f64, f64)>,
+    pub uv: Option<(f64, f64)>,
 }
 
 impl Intersection {
     pub fn new(distance: f64,
                intersection_point: Vec3,
                normal: Vec3,
-               shape: Box<Intersectable>)
+               shape: Box<dyn Intersectable>)
                -> Intersection {
         Intersection {
-            distance: distance,
-            intersection_point: intersection_point,
-            normal: normal,
-            shape: shape,
+            distance,
+            intersection_point,
+            normal,
+            shape,
+            barycentric: None,
+            uv: None,
+        }
+    }
+
+    pub fn with_barycentric(mut self, barycentric: (f64, f64, f64)) -> Intersection {
+        self.barycentric = Some(barycentric);
+        self
+    }
+
+    pub fn with_uv(mut self, uv: (f64, f64)) -> Intersection {
+        self.uv = Some(uv);
+        self
+    }
+}
+
+// The light that reaches rays that do not hit anything in the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    // The blue and white sky of the workshop.
+    Gradient,
+    Color(Color),
+}
+
+impl Background {
+    pub fn black() -> Background {
+        Background::Color(Color::black())
+    }
+
+    pub fn color(&self, ray: &Ray) -> Color {
+        match *self {
+            Background::Gradient => ::gradient(ray),
+            Background::Color(color) => color,
+        }
+    }
+
+    pub fn write_hash(&self, hasher: &mut SceneHasher) {
+        match *self {
+            Background::Gradient => hasher.write_str("gradient"),
+            Background::Color(color) => {
+                hasher.write_str("color");
+                hasher.write_color(color);
+            }
         }
     }
 }
 
 pub struct Scene {
-    pub shapes: Vec<Box<Intersectable>>,
+    // Not public, the BVH, the lights and the material ids are built from the shapes in `new`.
+    shapes: Vec<Box<dyn Intersectable>>,
+    bvh: Bvh,
+    // Indices of the shapes with an emissive material that can be sampled.
+    lights: Vec<usize>,
+    // The ids of the materials of the shapes, by their addresses.
+    materials: HashMap<usize, usize>,
+    pub background: Background,
 }
 
 impl Scene {
-    pub fn new(shapes: Vec<Box<Intersectable>>) -> Scene {
-        Scene { shapes: shapes }
+    pub fn new(shapes: Vec<Box<dyn Intersectable>>) -> Scene {
+        let bounds: Vec<Option<Aabb>> = shapes.iter().map(|shape| shape.aabb()).collect();
+        let lights = shapes.iter()
+            .enumerate()
+            .filter(|&(_, shape)| shape.is_samplable())
+            .filter(|&(_, shape)| shape.material().is_some_and(|material| material.is_emissive()))
+            .map(|(index, _)| index)
+            .collect();
+        let mut materials = HashMap::new();
+        for shape in shapes.iter() {
+            for material in shape.materials() {
+                let id = materials.len();
+                materials.entry(address(material)).or_insert(id);
+            }
+        }
+        Scene {
+            shapes,
+            bvh: Bvh::new(&bounds),
+            lights,
+            materials,
+            background: Background::Gradient,
+        }
     }
-}
 
-impl Intersectable for Scene {
-    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
-        let mut intersection: Option<Intersection> = None;
-        let mut closest_so_far: f64 = t_max;
+    pub fn with_background(mut self, background: Background) -> Scene {
+        self.background = background;
+        self
+    }
 
-        for shape in self.shapes.iter() {
-            match shape.intersects(ray, t_min, closest_so_far) {
-                Some(other_intersection) => {
-                    closest_so_far = other_intersection.distance;
-                    intersection = Some(other_intersection);
-                }
-                None => (),
+    pub fn shapes(&self) -> &[Box<dyn Intersectable>] {
+        &self.shapes
+    }
+
+    // The closest intersection along the ray, together with the index of the shape it hit.
+    pub fn intersects_shape(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, Intersection)> {
+        let mut shape = 0;
+        let intersection = self.bvh.intersects(ray, t_min, t_max, |index, closest_so_far| {
+            let intersection = self.shapes[index].intersects(ray, t_min, closest_so_far);
+            if intersection.is_some() {
+                shape = index;
             }
+            intersection
+        })?;
+        Some((shape, intersection))
+    }
+
+    // A number for every material of the scene, in the order the shapes use them.
+    pub fn material_id(&self, material: &dyn Material) -> Option<usize> {
+        self.materials.get(&address(material)).cloned()
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    // A direction from `origin` towards a random point on a light, every light is equally likely
+    // to be picked.
+    pub fn sample_light(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
         }
-        intersection
+        let index = (sampler.next_f64() * self.lights.len() as f64) as usize;
+        let light = self.lights[index.min(self.lights.len() - 1)];
+        self.shapes[light].sample_towards(origin, sampler)
+    }
+
+    // The probability density of `sample_light` picking `direction`.
+    pub fn light_pdf(&self, origin: Vec3, direction: Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.lights
+            .iter()
+            .map(|&light| self.shapes[light].pdf_towards(origin, direction))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+}
+
+fn address(material: &dyn Material) -> usize {
+    material as *const dyn Material as *const () as usize
+}
+
+impl Intersectable for Scene {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        self.bvh.intersects(ray, t_min, t_max, |index, closest_so_far| {
+            self.shapes[index].intersects(ray, t_min, closest_so_far)
+        })
     }
 
-    fn move_to(&self, _: Vec3) -> Box<Intersectable> {
+    fn move_to(&self, _: Vec3) -> Box<dyn Intersectable> {
         Box::new(Scene::new(vec![]))
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        self.bvh.aabb()
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("scene");
+        hasher.write_u64(self.shapes.len() as u64);
+        for shape in self.shapes.iter() {
+            shape.hash(hasher);
+        }
+        self.background.write_hash(hasher);
+    }
 }
 
 #[derive(Clone)]
 pub struct Sphere {
     pub origin: Vec3,
     pub radius: f64,
-    pub color: Color,
-    diffusiveness: Option<f64>,
-    refraction_index: Option<f64>,
+    pub material: Arc<dyn Material>,
 }
 
 impl Sphere {
     pub fn new(origin: Vec3, radius: f64, color: Color) -> Sphere {
-        Sphere {
-            origin: origin,
-            radius: radius,
-            color: color,
-            diffusiveness: None,
-            refraction_index: None,
-        }
+        Sphere::with_material(origin, radius, material::lambertian(color))
     }
 
     pub fn reflective(origin: Vec3, radius: f64, color: Color, diffusiveness: f64) -> Sphere {
-        Sphere {
-            origin: origin,
-            radius: radius,
-            color: color,
-            diffusiveness: Some(diffusiveness),
-            refraction_index: None,
-        }
+        Sphere::with_material(origin, radius, material::metal(color, diffusiveness))
     }
 
+    // Glass does not tint the light that passes through it, so the color is not used. It is kept
+    // for the scenes of the workshop, which give every sphere one.
     pub fn refractive(origin: Vec3, radius: f64, color: Color, refraction_index: f64) -> Sphere {
+        Sphere::with_material(origin, radius, material::dielectric(refraction_index))
+    }
+
+    pub fn texture(origin: Vec3, radius: f64, texture: &str) -> bmp::BmpResult<Sphere> {
+        let material = Textured::open(texture)?;
+        Ok(Sphere::with_material(origin, radius, Arc::new(material)))
+    }
+
+    pub fn emissive(origin: Vec3, radius: f64, radiance: Color) -> Sphere {
+        Sphere::with_material(origin, radius, material::emissive(radiance))
+    }
+
+    pub fn with_material(origin: Vec3, radius: f64, material: Arc<dyn Material>) -> Sphere {
         Sphere {
-            origin: origin,
-            radius: radius,
-            color: color,
-            diffusiveness: None,
-            refraction_index: Some(refraction_index),
+            origin,
+            radius,
+            material,
         }
     }
 
-    pub fn texture(origin: Vec3, radius: f64, texture: &'static str) -> Sphere {
-        panic!("Step 6a) open the image located at the `texture` path, and add a new field to \
-                the Sphere struct. The new field should be a reference counted pointer to the \
-                texture image. Additionally, add a new Sphere to the scene by using the \
-                Sphere::texture(path) constructor.");
+    // The cosine of the half angle of the cone the sphere covers seen from `origin`, or `None`
+    // if `origin` is inside the sphere.
+    fn cos_theta_max(&self, origin: Vec3) -> Option<f64> {
+        let squared_distance = (self.origin - origin).squared_length();
+        let squared_radius = self.radius * self.radius;
+        if squared_distance <= squared_radius {
+            return None;
+        }
+        Some((1.0 - squared_radius / squared_distance).sqrt())
     }
 }
 
@@ -136,34 +322,60 @@ impl Intersectable for Sphere {
         }
     }
 
-    fn scatter(&self, ray: &Ray, intersection: &Intersection) -> Option<(Color, Ray)> {
-        // Step 6b)
-        // Add a new if-expression to handle the case when a Sphere has a texture, then
-        // call (and implement) the scatter::texture() function.
-        if let Some(diffusiveness) = self.diffusiveness {
-            scatter::reflection(self.color, diffusiveness, ray, intersection)
-        } else if let Some(refraction_index) = self.refraction_index {
-            scatter::refraction(refraction_index, ray, intersection)
-        } else {
-            scatter::diffusive(self.color, intersection)
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn is_samplable(&self) -> bool {
+        true
+    }
+
+    // Picks a direction uniformly within the cone of directions that hit the sphere, see
+    // "Monte Carlo Techniques for Direct Lighting Calculations" (Shirley et al. 1996).
+    fn sample_towards(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let cos_theta_max = self.cos_theta_max(origin)?;
+        let (r1, r2) = sampler.next_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        let w = (self.origin - origin).normalize();
+        let (u, v) = w.orthonormal_basis();
+        Some(phi.cos() * sin_theta * u + phi.sin() * sin_theta * v + z * w)
+    }
+
+    fn pdf_towards(&self, origin: Vec3, direction: Vec3) -> f64 {
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) if self.intersects(&Ray::new(origin, direction), 0.0, f64::MAX)
+                .is_some() => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            _ => 0.0,
         }
     }
 
-    fn move_to(&self, vec: Vec3) -> Box<Intersectable> {
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
         Box::new(Sphere {
             origin: vec,
-            radius: self.radius,
-            color: self.color,
-            diffusiveness: self.diffusiveness,
-            refraction_index: self.refraction_index,
+            ..self.clone()
         })
     }
+
+    fn aabb(&self) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        let extent = Vec3::new(radius, radius, radius);
+        Some(Aabb::new(self.origin - extent, self.origin + extent))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("sphere");
+        hasher.write_vec3(self.origin);
+        hasher.write_f64(self.radius);
+        self.material.hash(hasher);
+    }
 }
 
 fn create_intersection(sphere: &Sphere, delta: f64, ray: &Ray) -> Option<Intersection> {
     let intersection_point = ray.point_along_direction(delta);
-    let surface_normal = panic!("Step 3b) Calculate the surface normal. Hint: The formula is \
-                                 available in the README");
+    let surface_normal = (intersection_point - sphere.origin) / sphere.radius;
     Some(Intersection::new(delta,
                            intersection_point,
                            surface_normal,
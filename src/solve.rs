@@ -0,0 +1,229 @@
+use std::f64::consts::PI;
+
+// Solves polynomial equations for the shapes whose surface is given by one, like the torus. All
+// solvers return the real roots from the smallest to the largest, a double root once.
+//
+// The cubic and the quartic are solved in closed form, after "Solving Quartics and Cubics for
+// Graphics" (Schwarze, Graphics Gems 1990). Closed forms lose precision when roots lie close
+// together, as for rays that graze a surface, so every root is polished with a few steps of
+// Newton's method on the original polynomial afterwards.
+
+// Coefficients this close to 0 are taken to be 0, relative to the coefficient of the highest
+// power after dividing by it.
+const EPSILON: f64 = 1e-9;
+
+const NEWTON_STEPS: u32 = 4;
+
+// The real roots of a x² + b x + c = 0.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+    let (p, q) = (b / (2.0 * a), c / a);
+    let discriminant = p * p - q;
+    if is_zero(discriminant) {
+        vec![-p]
+    } else if discriminant < 0.0 {
+        Vec::new()
+    } else {
+        // Adds numbers of the same sign only, the other root follows from their product, q.
+        let root = -p - p.signum() * discriminant.sqrt();
+        sorted(vec![root, q / root])
+    }
+}
+
+// The real roots of a x³ + b x² + c x + d = 0.
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_quadratic(b, c, d);
+    }
+    let (b, c, d) = (b / a, c / a, d / a);
+    // Substituting x = y - b/3 leaves y³ + 3 p y + 2 q = 0.
+    let shift = b / 3.0;
+    let p = (c - b * shift) / 3.0;
+    let q = (2.0 * shift * shift * shift - shift * c + d) / 2.0;
+    let discriminant = q * q + p * p * p;
+
+    let roots = if is_zero(discriminant) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // Three real roots, found with the trigonometric method.
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    } else {
+        let root = discriminant.sqrt();
+        vec![(root - q).cbrt() - (root + q).cbrt()]
+    };
+
+    polish(&[1.0, b, c, d], roots.iter().map(|y| y - shift).collect())
+}
+
+// The real roots of a x⁴ + b x³ + c x² + d x + e = 0.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_cubic(b, c, d, e);
+    }
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    // Substituting x = y - b/4 leaves y⁴ + p y² + q y + r = 0.
+    let shift = b / 4.0;
+    let shift_squared = shift * shift;
+    let p = c - 6.0 * shift_squared;
+    let q = d - 2.0 * c * shift + 8.0 * shift * shift_squared;
+    let r = e - d * shift + c * shift_squared - 3.0 * shift_squared * shift_squared;
+
+    let roots = if is_zero(r) {
+        // y (y³ + p y + q) = 0.
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // Splits the quartic into two quadratics (y² + z)² = (v y - q/2v)² with a root z of the
+        // resolvent cubic. The largest root keeps u² and v² from going negative by rounding.
+        // A cubic always has a real root, unless a coefficient was not a number to begin with.
+        let z = match solve_cubic(1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0).last() {
+            Some(&z) => z,
+            None => return Vec::new(),
+        };
+        let (u, v) = (square_root(z * z - r), square_root(2.0 * z - p));
+        match (u, v) {
+            (Some(u), Some(v)) => {
+                let v = if q < 0.0 { -v } else { v };
+                let mut roots = solve_quadratic(1.0, v, z - u);
+                roots.extend(solve_quadratic(1.0, -v, z + u));
+                roots
+            }
+            _ => Vec::new(),
+        }
+    };
+
+    polish(&[1.0, b, c, d, e], roots.iter().map(|y| y - shift).collect())
+}
+
+fn is_zero(value: f64) -> bool {
+    value.abs() < EPSILON
+}
+
+// The square root of a number that should not be negative, but may have become a little
+// negative by rounding.
+fn square_root(value: f64) -> Option<f64> {
+    if is_zero(value) {
+        Some(0.0)
+    } else if value > 0.0 {
+        Some(value.sqrt())
+    } else {
+        None
+    }
+}
+
+// Improves the roots of the polynomial with the coefficients from the highest power down with
+// Newton's method, then sorts them and merges the ones that turned out to be the same.
+fn polish(coefficients: &[f64], roots: Vec<f64>) -> Vec<f64> {
+    let roots = roots.into_iter()
+        .map(|mut x| {
+            for _ in 0..NEWTON_STEPS {
+                let (value, slope) = evaluate(coefficients, x);
+                // At a double root the slope goes to 0 along with the value, and the root is as
+                // good as it gets.
+                if slope == 0.0 || !(value / slope).is_finite() {
+                    break;
+                }
+                let next = x - value / slope;
+                if evaluate(coefficients, next).0.abs() >= value.abs() {
+                    break;
+                }
+                x = next;
+            }
+            x
+        })
+        .collect();
+    let mut roots = sorted(roots);
+    roots.dedup_by(|a, b| (*a - *b).abs() <= EPSILON * b.abs().max(1.0));
+    roots
+}
+
+// The value and the derivative of the polynomial at x, with Horner's method.
+fn evaluate(coefficients: &[f64], x: f64) -> (f64, f64) {
+    let mut value = 0.0;
+    let mut slope = 0.0;
+    for &coefficient in coefficients {
+        slope = slope * x + value;
+        value = value * x + coefficient;
+    }
+    (value, slope)
+}
+
+// Leaves out the roots that are not numbers, from coefficients that were not, like those of a ray
+// without a direction.
+fn sorted(roots: Vec<f64>) -> Vec<f64> {
+    let mut roots: Vec<f64> = roots.into_iter().filter(|root| root.is_finite()).collect();
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use hamcrest::prelude::*;
+    use super::{solve_quadratic, solve_cubic, solve_quartic};
+
+    fn assert_roots(roots: Vec<f64>, expected: &[f64]) {
+        assert_that!(roots.len(), is(equal_to(expected.len())));
+        for (root, &expected) in roots.iter().zip(expected) {
+            assert_that!(*root, is(close_to(expected, 1e-9)));
+        }
+    }
+
+    #[test]
+    fn quadratic_should_have_two_one_or_no_roots() {
+        assert_roots(solve_quadratic(2.0, -2.0, -4.0), &[-1.0, 2.0]);
+        assert_roots(solve_quadratic(1.0, -2.0, 1.0), &[1.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        // Without cancellation, the small root of x² - 10⁸ x + 1 is still precise.
+        assert_that!(solve_quadratic(1.0, -1e8, 1.0)[0], is(close_to(1e-8, 1e-20)));
+    }
+
+    #[test]
+    fn cubic_should_find_one_or_three_roots() {
+        // (x + 1)(x - 2)(x - 3)
+        assert_roots(solve_cubic(1.0, -4.0, 1.0, 6.0), &[-1.0, 2.0, 3.0]);
+        // (x - 1)(x² + 1)
+        assert_roots(solve_cubic(2.0, -2.0, 2.0, -2.0), &[1.0]);
+        // (x - 1)² (x + 2)
+        assert_roots(solve_cubic(1.0, 0.0, -3.0, 2.0), &[-2.0, 1.0]);
+    }
+
+    #[test]
+    fn quartic_should_find_all_real_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0]);
+        // (x + 0.5)(x - 7)(x² + 1)
+        assert_roots(solve_quartic(2.0, -13.0, -5.0, -13.0, -7.0), &[-0.5, 7.0]);
+        assert_roots(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0), &[]);
+        // A cubic in disguise.
+        assert_roots(solve_quartic(0.0, 1.0, -4.0, 1.0, 6.0), &[-1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn coefficients_that_are_not_numbers_should_have_no_roots() {
+        assert_roots(solve_quadratic(f64::NAN, 1.0, 1.0), &[]);
+        assert_roots(solve_cubic(1.0, f64::NAN, 0.0, 1.0), &[]);
+        assert_roots(solve_quartic(1.0, 0.0, f64::NAN, 0.0, -1.0), &[]);
+        assert_roots(solve_quartic(1.0, f64::INFINITY, 0.0, 0.0, -1.0), &[]);
+    }
+
+    #[test]
+    fn quartic_should_find_double_roots() {
+        // (x - 1)² (x + 2)²
+        let roots = solve_quartic(1.0, 2.0, -3.0, -4.0, 4.0);
+
+        assert_that!(roots.len(), is(equal_to(2)));
+        assert_that!(roots[0], is(close_to(-2.0, 1e-6)));
+        assert_that!(roots[1], is(close_to(1.0, 1e-6)));
+    }
+}
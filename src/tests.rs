@@ -1,3 +1,15 @@
+use prelude::*;
+
+// The path tracer of the workshop, light is only found by the rays that happen to hit it.
+fn trace(ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+    PathTracer::new().with_light_sampling(false).radiance(ray, scene, sampler)
+}
+
+// A ray straight down from 5 units up, for the tests of shapes that lie flat.
+pub fn down(x: f64, z: f64) -> Ray {
+    Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -1.0, 0.0))
+}
+
 mod step_1 {
     use hamcrest::prelude::*;
     use prelude::*;
@@ -43,11 +55,11 @@ mod step_2 {
     use prelude::*;
 
     #[test]
-    fn trace_ray_in_scene_should_return_gradient() {
+    fn path_tracer_should_return_gradient() {
         let scene = Scene::new(vec![]);
         let ray = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
 
-        let color = ::trace_ray_in_scene(&ray, &scene, 0);
+        let color = super::trace(&ray, &scene, &mut RandomSampler::new(0));
 
         assert_that!(color, is(equal_to(::gradient(&ray))));
     }
@@ -80,23 +92,118 @@ mod step_3 {
     }
 
     #[test]
-    fn c_trace_ray_in_scene_should_return_the_color_of_the_intersected_scene() {
+    fn c_path_tracer_should_return_the_color_of_the_intersected_scene() {
         let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1.0), 1.0, Color::black());
         let scene = Scene::new(vec![Box::new(sphere)]);
         let ray = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
 
-        let color = ::trace_ray_in_scene(&ray, &scene, 0);
+        let color = super::trace(&ray, &scene, &mut RandomSampler::new(0));
 
         assert_that!(color, is(equal_to(Color::black())));
     }
 
     #[test]
-    fn c_trace_ray_in_scene_should_return_gradient_if_ray_does_not_intersect() {
+    fn c_path_tracer_should_return_gradient_if_ray_does_not_intersect() {
         let scene = Scene::new(vec![]);
         let ray = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
 
-        let color = ::trace_ray_in_scene(&ray, &scene, 0);
+        let color = super::trace(&ray, &scene, &mut RandomSampler::new(0));
 
         assert_that!(color, is(equal_to(::gradient(&ray))));
     }
 }
+
+mod lights {
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    #[test]
+    fn a_ray_should_see_the_radiance_of_a_light() {
+        let light = Sphere::emissive(Vec3::new(0.0, 0.0, -2.0), 0.5, Color::new(4.0, 2.0, 1.0));
+        let scene = Scene::new(vec![Box::new(light)]).with_background(Background::black());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let color = super::trace(&ray, &scene, &mut RandomSampler::new(0));
+
+        assert_that!(color, is(equal_to(Color::new(4.0, 2.0, 1.0))));
+    }
+
+    #[test]
+    fn b_a_ray_that_misses_should_see_the_background() {
+        let scene = Scene::new(vec![]).with_background(Background::black());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let color = super::trace(&ray, &scene, &mut RandomSampler::new(0));
+
+        assert_that!(color, is(equal_to(Color::black())));
+    }
+
+    #[test]
+    fn c_a_surface_should_only_be_lit_by_lights_in_a_black_scene() {
+        let floor = Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, Color::white());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let dark = Scene::new(vec![Box::new(floor.clone())]).with_background(Background::black());
+        let lit = Scene::new(vec![Box::new(floor),
+                                  Box::new(Sphere::emissive(Vec3::new(0.0, 2.0, 0.0),
+                                                            1.0,
+                                                            Color::white()))])
+            .with_background(Background::black());
+
+        let mut sampler = RandomSampler::new(0);
+        let mut dark_color = Color::black();
+        let mut lit_color = Color::black();
+        for _ in 0..100 {
+            dark_color = dark_color + super::trace(&ray, &dark, &mut sampler);
+            lit_color = lit_color + super::trace(&ray, &lit, &mut sampler);
+        }
+
+        assert_that!(dark_color, is(equal_to(Color::black())));
+        assert_that!(lit_color.r, is(greater_than(0.0)));
+    }
+
+    #[test]
+    fn e_only_lights_that_can_be_sampled_should_be_sampled() {
+        let glowing_box = Cuboid::with_material(Vec3::new(-1.0, -1.0, -6.0),
+                                                Vec3::new(1.0, 1.0, -4.0),
+                                                ::material::emissive(Color::white()));
+        let light = Sphere::emissive(Vec3::new(0.0, 5.0, 0.0), 1.0, Color::white());
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        let unlit = Scene::new(vec![Box::new(glowing_box.clone())]);
+        let lit = Scene::new(vec![Box::new(glowing_box), Box::new(light.clone())]);
+
+        // The box is still seen by the rays that hit it, but is no light to send shadow rays to.
+        assert_that!(unlit.has_lights(), is(false));
+        assert_that!(lit.light_pdf(origin, up), is(equal_to(light.pdf_towards(origin, up))));
+        assert_that!(lit.sample_light(origin, &mut RandomSampler::new(0)).is_some(), is(true));
+    }
+
+    fn mean_and_variance<F: FnMut() -> Color>(num_samples: usize, mut trace: F) -> (f64, f64) {
+        let samples: Vec<f64> = (0..num_samples).map(|_| trace().r).collect();
+        let mean = samples.iter().sum::<f64>() / num_samples as f64;
+        let variance = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() /
+                       (num_samples - 1) as f64;
+        (mean, variance)
+    }
+
+    #[test]
+    fn d_light_sampling_should_converge_to_the_same_color_with_less_noise() {
+        let floor = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Color::new(0.5, 0.5, 0.5));
+        let light = Sphere::emissive(Vec3::new(0.0, 1.0, 0.0), 0.1, Color::new(100.0, 100.0, 100.0));
+        let scene = Scene::new(vec![Box::new(floor), Box::new(light)])
+            .with_background(Background::black());
+        let ray = Ray::new(Vec3::new(0.0, 0.5, 1.0), Vec3::new(0.0, -0.5, -1.0).normalize());
+        let num_samples = 20000;
+        let mut sampler = RandomSampler::new(0);
+
+        let (naive_mean, naive_variance) =
+            mean_and_variance(num_samples, || super::trace(&ray, &scene, &mut sampler));
+        let (mean, variance) =
+            mean_and_variance(num_samples, || PathTracer::new().radiance(&ray, &scene, &mut sampler));
+
+        let standard_error = ((naive_variance + variance) / num_samples as f64).sqrt();
+        assert_that!((mean - naive_mean).abs(), is(less_than(5.0 * standard_error)));
+        assert_that!(variance * 10.0, is(less_than(naive_variance)));
+    }
+}
@@ -0,0 +1,115 @@
+use prelude::*;
+
+// Turns the radiance of a pixel, which can be any value, into a color between 0 and 1 that can be
+// shown. The exposure (in stops, EV) scales the radiance first, then the operator compresses the
+// highlights. `Clamp` leaves the radiance as it is, so everything above 1 clips.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMap {
+    #[default]
+    Clamp,
+    // "Photographic Tone Reproduction for Digital Images" (Reinhard et al. 2002), on the luminance
+    // so colors keep their hue.
+    Reinhard,
+    // Reinhard with the luminance that maps to white, rather than only reaching it at infinity.
+    ExtendedReinhard { white: f64 },
+    // The filmic curve of Uncharted 2, see "Filmic Tonemapping Operators" (Hable 2010).
+    Hable,
+    // The ACES filmic curve, as fitted by Narkowicz in "ACES Filmic Tone Mapping Curve" (2016).
+    Aces,
+}
+
+impl ToneMap {
+    // The names accepted by `from_name`.
+    pub const NAMES: [&'static str; 5] = ["clamp", "reinhard", "extended-reinhard", "hable", "aces"];
+
+    pub fn from_name(name: &str) -> Option<ToneMap> {
+        match name {
+            "clamp" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "extended-reinhard" => Some(ToneMap::ExtendedReinhard { white: 4.0 }),
+            "hable" => Some(ToneMap::Hable),
+            "aces" => Some(ToneMap::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard { white } => {
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMap::Hable => {
+                // Hable brightens the radiance by 2 and maps a radiance of 11.2 to white.
+                let white = hable(11.2);
+                Color::new(hable(2.0 * color.r) / white,
+                           hable(2.0 * color.g) / white,
+                           hable(2.0 * color.b) / white)
+            }
+            ToneMap::Aces => Color::new(aces(color.r), aces(color.g), aces(color.b)),
+        }
+    }
+}
+
+// Applies the exposure and the operator to every pixel, after the samples are added up and before
+// the colors are sRGB encoded and quantised.
+pub fn tone_map(pixels: &[Color], exposure: f64, operator: ToneMap) -> Vec<Color> {
+    let scale = 2f64.powf(exposure);
+    pixels.iter().map(|&color| operator.apply(color * scale)).collect()
+}
+
+fn scale_luminance<F: Fn(f64) -> f64>(color: Color, map: F) -> Color {
+    let luminance = color.luminance();
+    if luminance > 0.0 {
+        color * (map(luminance) / luminance)
+    } else {
+        Color::black()
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+fn aces(x: f64) -> f64 {
+    let x = 0.6 * x.max(0.0);
+    let mapped = x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+    mapped.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use super::{ToneMap, tone_map};
+
+    #[test]
+    fn operators_should_keep_highlights_below_white_and_the_order_of_values() {
+        for name in ToneMap::NAMES.iter().filter(|&&name| name != "clamp") {
+            let operator = ToneMap::from_name(name).unwrap();
+            let mut previous = -1.0;
+            for &value in [0.0, 0.05, 0.5, 1.0, 3.0].iter() {
+                let mapped = operator.apply(Color::white() * value).g;
+
+                assert_that!(mapped, is(greater_than(previous)));
+                assert_that!(mapped, is(less_than_or_equal_to(1.0 + 1e-9)));
+                previous = mapped;
+            }
+        }
+        // Extended Reinhard and Hable reach white at a finite radiance, the others never do.
+        assert_that!(ToneMap::Reinhard.apply(Color::white() * 100.0).g, is(less_than(1.0)));
+        assert_that!(ToneMap::Aces.apply(Color::white() * 100.0).g, is(less_than_or_equal_to(1.0)));
+    }
+
+    #[test]
+    fn exposure_should_count_in_stops() {
+        let pixels = [Color::new(0.25, 0.5, 0.0)];
+
+        assert_that!(tone_map(&pixels, 1.0, ToneMap::Clamp), is(equal_to(vec![Color::new(0.5, 1.0, 0.0)])));
+        assert_that!(tone_map(&pixels, 0.0, ToneMap::Clamp), is(equal_to(pixels.to_vec())));
+        let white = ToneMap::ExtendedReinhard { white: 4.0 }.apply(Color::white() * 4.0);
+        assert_that!(white.r, is(close_to(1.0, 1e-12)));
+    }
+}
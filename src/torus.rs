@@ -0,0 +1,250 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use aabb::Aabb;
+use material::{self, Material};
+use scene::Intersection;
+use solve::solve_quartic;
+use prelude::*;
+
+// A ring around `center`: all points at `minor_radius` from the circle of `major_radius` at
+// right angles to `axis`, like a doughnut. The texture coordinates go around the axis (u) and
+// around the tube (v).
+#[derive(Clone)]
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64, color: Color) -> Torus {
+        Torus::with_material(center, axis, major_radius, minor_radius, material::lambertian(color))
+    }
+
+    pub fn reflective(center: Vec3,
+                      axis: Vec3,
+                      major_radius: f64,
+                      minor_radius: f64,
+                      color: Color,
+                      diffusiveness: f64)
+                      -> Torus {
+        Torus::with_material(center,
+                             axis,
+                             major_radius,
+                             minor_radius,
+                             material::metal(color, diffusiveness))
+    }
+
+    pub fn refractive(center: Vec3,
+                      axis: Vec3,
+                      major_radius: f64,
+                      minor_radius: f64,
+                      refraction_index: f64)
+                      -> Torus {
+        Torus::with_material(center,
+                             axis,
+                             major_radius,
+                             minor_radius,
+                             material::dielectric(refraction_index))
+    }
+
+    pub fn with_material(center: Vec3,
+                         axis: Vec3,
+                         major_radius: f64,
+                         minor_radius: f64,
+                         material: Arc<dyn Material>)
+                         -> Torus {
+        Torus {
+            center,
+            axis: axis.normalize(),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+
+    // Two directions at right angles to the axis and each other, the torus lies in their plane.
+    fn basis(&self) -> (Vec3, Vec3) {
+        self.axis.orthonormal_basis()
+    }
+}
+
+impl Intersectable for Torus {
+    // The points p of the surface solve (|p|² + R² - r²)² = 4 R² (p.x² + p.y²) in the space of
+    // the torus, with the axis along z. Along the ray that is a quartic in its distance. The
+    // quartic is solved from the point where the ray enters the bounding sphere of the torus with
+    // a unit direction, which keeps its coefficients small even for rays from far away.
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let (tangent, bitangent) = self.basis();
+        let local = |v: Vec3| Vec3::new(v.dot(tangent), v.dot(bitangent), v.dot(self.axis));
+        let (big, small) = (self.major_radius, self.minor_radius);
+
+        let scale = ray.direction.length();
+        let d = local(ray.direction) / scale;
+        let origin = local(ray.origin - self.center);
+        // The bounding sphere, the ray misses the torus if it misses the sphere.
+        let bound = big + small;
+        let b = origin.dot(d);
+        let discriminant = b * b - (origin.dot(origin) - bound * bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let start = (-b - discriminant.sqrt()).max(0.0);
+        let o = origin + start * d;
+
+        let k = o.dot(o) + big * big - small * small;
+        let n = o.dot(d);
+        let four_big = 4.0 * big * big;
+        let roots = solve_quartic(1.0,
+                                  4.0 * n,
+                                  4.0 * n * n + 2.0 * k - four_big * (d.x * d.x + d.y * d.y),
+                                  4.0 * n * k - 2.0 * four_big * (o.x * d.x + o.y * d.y),
+                                  k * k - four_big * (o.x * o.x + o.y * o.y));
+        let delta = roots.into_iter()
+            .map(|s| (start + s) / scale)
+            .find(|&delta| delta > t_min && delta < t_max)?;
+
+        let p = origin + delta * scale * d;
+        // The normal points away from the closest point on the circle in the middle of the tube.
+        let ring = (p.x * p.x + p.y * p.y).sqrt();
+        let (around_axis, around_tube) = (p.y.atan2(p.x), p.z.atan2(ring - big));
+        let center = Vec3::new(around_axis.cos(), around_axis.sin(), 0.0) * big;
+        let normal = (p - center).normalize();
+        let uv = (turns(around_axis), turns(around_tube));
+
+        Some(Intersection::new(delta,
+                               ray.point_along_direction(delta),
+                               normal.x * tangent + normal.y * bitangent + normal.z * self.axis,
+                               Box::new(self.clone()))
+            .with_uv(uv))
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        Box::new(Torus { center: vec, ..self.clone() })
+    }
+
+    // The circle in the middle of the tube reaches out R (1 - a²)^0.5 along an axis a of the
+    // scene, the tube adds r on either side.
+    fn aabb(&self) -> Option<Aabb> {
+        let a = self.axis;
+        let extent = Vec3::new(self.major_radius * (1.0 - a.x * a.x).max(0.0).sqrt() + self.minor_radius,
+                               self.major_radius * (1.0 - a.y * a.y).max(0.0).sqrt() + self.minor_radius,
+                               self.major_radius * (1.0 - a.z * a.z).max(0.0).sqrt() + self.minor_radius);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("torus");
+        hasher.write_vec3(self.center);
+        hasher.write_vec3(self.axis);
+        hasher.write_f64(self.major_radius);
+        hasher.write_f64(self.minor_radius);
+        self.material.hash(hasher);
+    }
+}
+
+// An angle in turns, from 0 to 1.
+fn turns(angle: f64) -> f64 {
+    let turns = angle / (2.0 * PI);
+    if turns < 0.0 { turns + 1.0 } else { turns }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+    use tests::down;
+
+    // Lies flat in the plane y = 0, 5 units away.
+    fn torus() -> Torus {
+        Torus::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, Color::white())
+    }
+
+    #[test]
+    fn ray_should_hit_the_top_of_the_tube() {
+        let i = torus().intersects(&down(2.0, -5.0), 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(4.5, 1e-9)));
+        assert_that!(i.normal.y, is(close_to(1.0, 1e-9)));
+    }
+
+    #[test]
+    fn ray_should_pass_through_the_hole() {
+        let through_hole = down(0.0, -5.0);
+        let along_plane = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let from_the_middle = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0));
+
+        assert_that!(torus().intersects(&through_hole, 0.0, 1000.0).is_none(), is(true));
+        // Through the tube, the hole and the tube again.
+        assert_that!(torus().intersects(&along_plane, 0.0, 1000.0).unwrap().distance,
+                     is(close_to(2.5, 1e-9)));
+        assert_that!(torus().intersects(&along_plane, 3.0, 1000.0).unwrap().distance,
+                     is(close_to(3.5, 1e-9)));
+        assert_that!(torus().intersects(&along_plane, 4.0, 1000.0).unwrap().distance,
+                     is(close_to(6.5, 1e-9)));
+        let inner = torus().intersects(&from_the_middle, 0.0, 1000.0).unwrap();
+        assert_that!(inner.distance, is(close_to(1.5, 1e-9)));
+        assert_that!(inner.normal.x, is(close_to(-1.0, 1e-9)));
+    }
+
+    #[test]
+    fn grazing_ray_should_touch_the_top_of_the_tube() {
+        // Along the top of the torus, touching it where it crosses the tube.
+        let grazing = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let above = Ray::new(Vec3::new(0.0, 0.5 + 1e-4, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let below = Ray::new(Vec3::new(0.0, 0.5 - 1e-4, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = torus().intersects(&grazing, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(3.0, 1e-6)));
+        assert_that!(i.normal.y, is(close_to(1.0, 1e-6)));
+        assert_that!(torus().intersects(&above, 0.0, 1000.0).is_none(), is(true));
+        // Just below, it goes through the top of the tube, 0.01 on either side of the middle.
+        assert_that!(torus().intersects(&below, 0.0, 1000.0).unwrap().distance,
+                     is(close_to(2.99, 1e-4)));
+    }
+
+    #[test]
+    fn grazing_ray_should_touch_the_outside_of_the_torus() {
+        // Tangent to the outer edge of the torus, from far away and with a long direction.
+        let ray = Ray::new(Vec3::new(-1000.0, 0.0, -2.5), Vec3::new(10.0, 0.0, 0.0));
+
+        let i = torus().intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(100.0, 1e-6)));
+        assert_that!(i.normal.z, is(close_to(1.0, 1e-6)));
+    }
+
+    #[test]
+    fn ray_without_a_direction_should_miss() {
+        let ray = Ray::new(Vec3::new(0.0, 5.0, -3.0), Vec3::new(0.0, 0.0, 0.0));
+
+        assert_that!(torus().intersects(&ray, 0.0, 1000.0).is_none(), is(true));
+    }
+
+    #[test]
+    fn torus_around_z_should_have_texture_coordinates_and_bounds() {
+        let torus = Torus::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 2.0, 0.5, Color::white());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let outside = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+
+        let hole = torus.intersects(&ray, 0.0, 1000.0);
+        let i = torus.intersects(&outside, 0.0, 1000.0).unwrap();
+
+        assert_that!(hole.is_none(), is(true));
+        // The angles around the axis and around the tube both start on the outside, along x.
+        let (u, v) = i.uv.unwrap();
+        assert_that!(i.distance, is(close_to(2.5, 1e-9)));
+        assert_that!(u, is(close_to(0.0, 1e-9)));
+        assert_that!(v, is(close_to(0.0, 1e-9)));
+        let aabb = torus.aabb().unwrap();
+        assert_that!(aabb.max, is(equal_to(Vec3::new(2.5, 2.5, 0.5))));
+    }
+}
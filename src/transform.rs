@@ -0,0 +1,219 @@
+use aabb::Aabb;
+use material::Material;
+use scene::Intersection;
+use prelude::*;
+
+// A shape moved, turned and stretched by an affine transform, without touching the shape itself.
+// Rays are brought into the space of the shape by the inverse transform instead, so one shape can
+// be placed many times: the copies of a `Mesh` share its triangles, so a thousand transformed
+// copies of it cost no more memory than one.
+#[derive(Clone)]
+pub struct Transformed<T: Intersectable> {
+    pub shape: T,
+    transform: Matrix4,
+    inverse: Matrix4,
+}
+
+impl<T: Intersectable + Clone + 'static> Transformed<T> {
+    // Places the shape with the transform. With `Matrix4::translate(offset) * Matrix4::rot(axis,
+    // angle) * Matrix4::scale(factors)` the shape is scaled first, then turned, then moved.
+    // `None` if the transform can not be undone, like a scale by 0.
+    pub fn new(shape: T, transform: Matrix4) -> Option<Transformed<T>> {
+        let inverse = transform.inverse()?;
+        Some(Transformed { shape, transform, inverse })
+    }
+
+    pub fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    // Applies another transform after the one the shape already has.
+    pub fn transformed(&self, transform: Matrix4) -> Option<Transformed<T>> {
+        Transformed::new(self.shape.clone(), transform * self.transform)
+    }
+
+    // The ray in the space of the shape. The direction is not normalized, so distances along the
+    // ray are the same in both spaces.
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray::new(self.inverse * ray.origin, self.inverse.transform_direction(ray.direction))
+    }
+}
+
+impl<T: Intersectable + Clone + 'static> Intersectable for Transformed<T> {
+    // The hit part of the shape stays in the intersection, it scatters the light with its
+    // material, which does not care about the space the shape was hit in.
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let intersection = self.shape.intersects(&self.local_ray(ray), t_min, t_max)?;
+        let normal = self.inverse.transpose().transform_direction(intersection.normal).normalize();
+        Some(Intersection {
+            intersection_point: ray.point_along_direction(intersection.distance),
+            normal,
+            ..intersection
+        })
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        self.shape.material()
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.shape.materials()
+    }
+
+    fn is_samplable(&self) -> bool {
+        self.shape.is_samplable()
+    }
+
+    // The directions the shape samples in its own space are transformed like the rays. That
+    // changes how densely they cover the sphere of directions, for a linear map A the density
+    // grows by |A w|³ / |det A| for a unit direction w of the shape.
+    fn sample_towards(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let direction = self.shape.sample_towards(self.inverse * origin, sampler)?;
+        Some(self.transform.transform_direction(direction))
+    }
+
+    fn pdf_towards(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let local_direction = self.inverse.transform_direction(direction).normalize();
+        let pdf = self.shape.pdf_towards(self.inverse * origin, local_direction);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+        let stretch = self.transform.transform_direction(local_direction).length();
+        pdf * stretch * stretch * stretch / self.transform.determinant().abs()
+    }
+
+    // Moves the center of the bounds of the transformed shape to `vec`.
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        let center = self.aabb().map_or(self.transform * Vec3::new(0.0, 0.0, 0.0), |aabb| aabb.centroid());
+        let offset = vec - center;
+        Box::new(Transformed {
+            shape: self.shape.clone(),
+            transform: Matrix4::translate(offset) * self.transform,
+            inverse: self.inverse * Matrix4::translate(-1.0 * offset),
+        })
+    }
+
+    // The bounds of the transformed corners of the bounds of the shape.
+    fn aabb(&self) -> Option<Aabb> {
+        let aabb = self.shape.aabb()?;
+        let mut corners = Vec::with_capacity(8);
+        for &x in [aabb.min.x, aabb.max.x].iter() {
+            for &y in [aabb.min.y, aabb.max.y].iter() {
+                for &z in [aabb.min.z, aabb.max.z].iter() {
+                    corners.push(self.transform * Vec3::new(x, y, z));
+                }
+            }
+        }
+        Some(Aabb::from_points(&corners))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("transformed");
+        hasher.write_matrix(self.transform);
+        self.shape.hash(hasher);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Color::white())
+    }
+
+    #[test]
+    fn translated_sphere_should_be_hit_where_it_was_moved_to() {
+        let sphere = Transformed::new(unit_sphere(), Matrix4::translate(Vec3::new(0.0, 0.0, -5.0))).unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0));
+
+        let i = sphere.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(close_to(2.0, 1e-12)));
+        assert_that!(i.intersection_point, is(equal_to(Vec3::new(0.0, 0.0, -4.0))));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 0.0, 1.0))));
+    }
+
+    #[test]
+    fn stretched_sphere_should_have_normals_at_right_angles_to_its_surface() {
+        // An ellipsoid twice as wide as it is high.
+        let ellipsoid = Transformed::new(unit_sphere(), Matrix4::scale(Vec3::new(2.0, 1.0, 1.0))).unwrap();
+        let ray = Ray::new(Vec3::new(2f64.sqrt(), 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        let i = ellipsoid.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        // The point (2^0.5, 0.5^0.5) of the ellipse x²/4 + y² = 1 has the gradient (x/2, 2y).
+        let expected = Vec3::new(0.5f64.sqrt(), 2.0 * 0.5f64.sqrt(), 0.0).normalize();
+        assert_that!(i.distance, is(close_to(5.0 - 0.5f64.sqrt(), 1e-12)));
+        assert_that!(i.normal.x, is(close_to(expected.x, 1e-12)));
+        assert_that!(i.normal.y, is(close_to(expected.y, 1e-12)));
+        let aabb = ellipsoid.aabb().unwrap();
+        assert_that!(aabb.max, is(equal_to(Vec3::new(2.0, 1.0, 1.0))));
+    }
+
+    #[test]
+    fn instances_should_share_the_geometry_of_a_mesh() {
+        let positions = vec![Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let face = MeshFace { positions: [0, 1, 2], normals: None, uvs: None, material: 0 };
+        let triangle = Mesh::new(positions,
+                                 vec![],
+                                 vec![],
+                                 vec![face],
+                                 vec![::material::lambertian(Color::white())])
+            .unwrap();
+        let left = Transformed::new(triangle.clone(), Matrix4::translate(Vec3::new(-3.0, 0.0, -5.0)))
+            .unwrap();
+        let turned = left.transformed(Matrix4::rot(Axis::Y, PI)).unwrap();
+
+        let ray = Ray::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let behind = Ray::new(Vec3::new(3.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert_that!(left.intersects(&ray, 0.0, 1000.0).unwrap().distance, is(close_to(5.0, 1e-12)));
+        // Half a turn around the y axis takes it to the other side, facing the other way.
+        let i = turned.intersects(&behind, 0.0, 1000.0).unwrap();
+        assert_that!(i.distance, is(close_to(5.0, 1e-9)));
+        assert_that!(i.normal.z, is(close_to(-1.0, 1e-9)));
+        assert_that!(turned.materials().len(), is(equal_to(1)));
+    }
+
+    #[test]
+    fn shape_should_not_be_transformed_by_a_transform_that_can_not_be_undone() {
+        let flat = Matrix4::scale(Vec3::new(1.0, 0.0, 1.0));
+
+        let sphere = Transformed::new(unit_sphere(), Matrix4::identity()).unwrap();
+
+        assert_that!(Transformed::new(unit_sphere(), flat).is_none(), is(true));
+        assert_that!(sphere.transformed(flat).is_none(), is(true));
+    }
+
+    #[test]
+    fn moved_shape_should_keep_its_rotation_and_scale() {
+        let sphere = Transformed::new(unit_sphere(), Matrix4::scale(Vec3::new(2.0, 2.0, 2.0))).unwrap();
+
+        let moved = sphere.move_to(Vec3::new(0.0, 0.0, -10.0));
+
+        let aabb = moved.aabb().unwrap();
+        assert_that!(aabb.min, is(equal_to(Vec3::new(-2.0, -2.0, -12.0))));
+        assert_that!(aabb.max, is(equal_to(Vec3::new(2.0, 2.0, -8.0))));
+    }
+
+    #[test]
+    fn light_pdf_should_follow_the_transform() {
+        // A sphere scaled by 2 from 10 away looks like a sphere of radius 2 from 10 away.
+        let light = Transformed::new(Sphere::emissive(Vec3::new(0.0, 0.0, 0.0), 1.0, Color::white()),
+                                     Matrix4::translate(Vec3::new(0.0, 0.0, -10.0)) *
+                                     Matrix4::scale(Vec3::new(2.0, 2.0, 2.0)))
+            .unwrap();
+        let big = Sphere::emissive(Vec3::new(0.0, 0.0, -10.0), 2.0, Color::white());
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let direction = Vec3::new(0.05, 0.0, -1.0);
+
+        assert_that!(light.pdf_towards(origin, direction),
+                     is(close_to(big.pdf_towards(origin, direction), 1e-9)));
+        let mut sampler = RandomSampler::new(1);
+        let sample = light.sample_towards(origin, &mut sampler).unwrap();
+        assert_that!(light.intersects(&Ray::new(origin, sample), 0.0, 1000.0).is_some(), is(true));
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use aabb::Aabb;
+use material::{self, Material};
+use scene::Intersection;
+use prelude::*;
+
+const EPSILON: f64 = 0.00000001;
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, color: Color) -> Triangle {
+        Triangle::with_material(a, b, c, material::lambertian(color))
+    }
+
+    pub fn reflective(a: Vec3, b: Vec3, c: Vec3, color: Color, diffusiveness: f64) -> Triangle {
+        Triangle::with_material(a, b, c, material::metal(color, diffusiveness))
+    }
+
+    pub fn refractive(a: Vec3, b: Vec3, c: Vec3, refraction_index: f64) -> Triangle {
+        Triangle::with_material(a, b, c, material::dielectric(refraction_index))
+    }
+
+    pub fn emissive(a: Vec3, b: Vec3, c: Vec3, radiance: Color) -> Triangle {
+        Triangle::with_material(a, b, c, material::emissive(radiance))
+    }
+
+    pub fn with_material(a: Vec3, b: Vec3, c: Vec3, material: Arc<dyn Material>) -> Triangle {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(&self, a: Vec3, b: Vec3, c: Vec3) -> Triangle {
+        Triangle { normals: Some([a.normalize(), b.normalize(), c.normalize()]), ..self.clone() }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.vertices[0] + self.vertices[1] + self.vertices[2]) / 3.0
+    }
+
+    fn normal_at(&self, u: f64, v: f64) -> Vec3 {
+        match self.normals {
+            Some([a, b, c]) => ((1.0 - u - v) * a + u * b + v * c).normalize(),
+            None => {
+                let edge1 = self.vertices[1] - self.vertices[0];
+                let edge2 = self.vertices[2] - self.vertices[0];
+                edge1.cross(edge2).normalize()
+            }
+        }
+    }
+}
+
+impl Intersectable for Triangle {
+    fn intersects(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Intersection> {
+        let [a, b, c] = self.vertices;
+        let (delta, u, v) = intersect_triangle(ray, a, b, c, t_min, t_max)?;
+        Some(Intersection::new(delta,
+                               ray.point_along_direction(delta),
+                               self.normal_at(u, v),
+                               Box::new(self.clone()))
+            .with_barycentric((1.0 - u - v, u, v)))
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(&*self.material)
+    }
+
+    fn is_samplable(&self) -> bool {
+        true
+    }
+
+    // Picks a point uniformly on the area of the triangle, see "Shape Distributions" (Osada et
+    // al. 2002).
+    fn sample_towards(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let [a, b, c] = self.vertices;
+        let (s, t) = sampler.next_2d();
+        let s = s.sqrt();
+        let point = (1.0 - s) * a + s * (1.0 - t) * b + s * t * c;
+        Some(point - origin)
+    }
+
+    // Converts the density per area, 1 / area, to a density per solid angle seen from `origin`.
+    fn pdf_towards(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let [a, b, c] = self.vertices;
+        let direction = direction.normalize();
+        match intersect_triangle(&Ray::new(origin, direction), a, b, c, 0.0, f64::MAX) {
+            Some((distance, _, _)) => {
+                let cross = (b - a).cross(c - a);
+                let area = 0.5 * cross.length();
+                let cosine = direction.dot(cross.normalize()).abs();
+                distance * distance / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn move_to(&self, vec: Vec3) -> Box<dyn Intersectable> {
+        let offset = vec - self.centroid();
+        Box::new(Triangle {
+            vertices: [self.vertices[0] + offset,
+                       self.vertices[1] + offset,
+                       self.vertices[2] + offset],
+            ..self.clone()
+        })
+    }
+
+    fn aabb(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&self.vertices))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.write_str("triangle");
+        for &vertex in self.vertices.iter() {
+            hasher.write_vec3(vertex);
+        }
+        if let Some(normals) = self.normals {
+            for &normal in normals.iter() {
+                hasher.write_vec3(normal);
+            }
+        }
+        self.material.hash(hasher);
+    }
+}
+
+// Möller–Trumbore: solves origin + t * direction = (1 - u - v) * a + u * b + v * c
+// for (t, u, v) without precomputing the plane of the triangle.
+pub fn intersect_triangle(ray: &Ray,
+                          a: Vec3,
+                          b: Vec3,
+                          c: Vec3,
+                          t_min: f64,
+                          t_max: f64)
+                          -> Option<(f64, f64, f64)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = ray.origin - a;
+    let u = s.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let delta = edge2.dot(q) * inverse_determinant;
+    if delta < t_max && delta > t_min {
+        Some((delta, u, v))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hamcrest::prelude::*;
+    use prelude::*;
+
+    fn triangle() -> Triangle {
+        Triangle::new(Vec3::new(-1.0, -1.0, -1.0),
+                      Vec3::new(1.0, -1.0, -1.0),
+                      Vec3::new(-1.0, 1.0, -1.0),
+                      Color::white())
+    }
+
+    #[test]
+    fn ray_should_intersect_with_triangle() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = triangle().intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(1.0)));
+        assert_that!(i.intersection_point, is(equal_to(Vec3::new(0.0, 0.0, -1.0))));
+        assert_that!(i.normal, is(equal_to(Vec3::new(0.0, 0.0, 1.0))));
+        assert_that!(i.barycentric, is(equal_to(Some((0.0, 0.5, 0.5)))));
+    }
+
+    #[test]
+    fn ray_should_intersect_with_back_of_triangle() {
+        let ray = Ray::new(Vec3::new(-0.5, -0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+
+        let i = triangle().intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(1.0)));
+        assert_that!(i.barycentric, is(equal_to(Some((0.5, 0.25, 0.25)))));
+    }
+
+    #[test]
+    fn ray_should_miss_outside_of_triangle() {
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert_that!(triangle().intersects(&ray, 0.0, 1000.0).is_none(), is(true));
+    }
+
+    #[test]
+    fn ray_parallel_to_triangle_should_miss() {
+        let ray = Ray::new(Vec3::new(-2.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 0.0));
+
+        assert_that!(triangle().intersects(&ray, 0.0, 1000.0).is_none(), is(true));
+    }
+
+    #[test]
+    fn intersection_should_interpolate_vertex_normals() {
+        let triangle = triangle().with_normals(Vec3::new(1.0, 0.0, 1.0),
+                                               Vec3::new(0.0, 0.0, 1.0),
+                                               Vec3::new(-1.0, 0.0, 1.0));
+        let ray = Ray::new(Vec3::new(-0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = triangle.intersects(&ray, 0.0, 1000.0).unwrap();
+        let (a, b, c) = i.barycentric.unwrap();
+
+        assert_that!(a + b + c, is(equal_to(1.0)));
+        assert_that!(i.normal.x, is(less_than(0.0)));
+        assert_that!((i.normal.length() - 1.0).abs(), is(less_than(1e-12)));
+    }
+
+    #[test]
+    fn triangle_should_move_its_centroid() {
+        let moved = triangle().move_to(Vec3::new(0.0, 0.0, -5.0));
+        let ray = Ray::new(Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let i = moved.intersects(&ray, 0.0, 1000.0).unwrap();
+
+        assert_that!(i.distance, is(equal_to(5.0)));
+    }
+
+    #[test]
+    fn sampled_directions_should_hit_the_triangle() {
+        let origin = Vec3::new(-1.0 / 3.0, -1.0 / 3.0, 0.0);
+
+        let mut sampler = RandomSampler::new(0);
+        for _ in 0..100 {
+            let direction = triangle().sample_towards(origin, &mut sampler).unwrap();
+            assert_that!(triangle().intersects(&Ray::new(origin, direction), 0.0, 1000.0).is_some(),
+                         is(true));
+        }
+        let pdf = triangle().pdf_towards(origin, Vec3::new(0.0, 0.0, -1.0));
+        assert_that!((pdf - 0.5).abs(), is(less_than(1e-12)));
+    }
+}
@@ -11,7 +11,7 @@ pub struct Vec3 {
 
 impl Vec3 {
     pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
-        Vec3 { x: x, y: y, z: z }
+        Vec3 { x, y, z }
     }
 
     pub fn length(&self) -> f64 {
@@ -41,6 +41,16 @@ impl Vec3 {
     pub fn invert(&self) -> Vec3 {
         Vec3::new(-self.x, -self.y, -self.z)
     }
+
+    // Two unit vectors that together with this (unit) vector form an orthonormal basis, see
+    // "Building an Orthonormal Basis, Revisited" (Duff et al. 2017).
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let sign = 1.0f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+         Vec3::new(b, sign + self.y * self.y * a, -self.y))
+    }
 }
 
 impl PartialEq for Vec3 {
@@ -53,7 +63,7 @@ impl Add<Vec3> for Vec3 {
     type Output = Vec3;
 
     fn add(self, vec: Vec3) -> Vec3 {
-        panic!("Step 1a) Implement vector with vector addition")
+        Vec3::new(self.x + vec.x, self.y + vec.y, self.z + vec.z)
     }
 }
 
@@ -77,7 +87,7 @@ impl Mul<f64> for Vec3 {
     type Output = Vec3;
 
     fn mul(self, val: f64) -> Vec3 {
-        panic!("Step 1b) Implement vector with scalar multiplication")
+        Vec3::new(self.x * val, self.y * val, self.z * val)
     }
 }
 
@@ -85,7 +95,7 @@ impl Mul<Vec3> for f64 {
     type Output = Vec3;
 
     fn mul(self, vec: Vec3) -> Vec3 {
-        panic!("Step 1c) Implement scalar with vector multiplication")
+        vec * self
     }
 }
 
@@ -124,7 +134,7 @@ impl Div<f64> for Vec3 {
 impl Index<u32> for Vec3 {
     type Output = f64;
 
-    fn index<'a>(&'a self, index: u32) -> &'a f64 {
+    fn index(&self, index: u32) -> &f64 {
         match index {
             0 => &self.x,
             1 => &self.y,